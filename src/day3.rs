@@ -54,21 +54,12 @@ fn part1(input: &Input) -> i64 {
     parse_numbers(input)
         .into_iter()
         .filter(|&Number { l, r, i, .. }| {
-            let mut has_symbol = (input[i][l] != b'.' && !input[i][l].is_ascii_digit())
+            let has_symbol = (input[i][l] != b'.' && !input[i][l].is_ascii_digit())
                 || (input[i][r] != b'.' && !input[i][r].is_ascii_digit());
 
-            for p in l..r + 1 {
-                if i > 0 {
-                    has_symbol = has_symbol || input[i - 1][p] != b'.';
-                }
-                if i + 1 < m {
-                    has_symbol = has_symbol || input[i + 1][p] != b'.';
-                }
-                if has_symbol {
-                    break;
-                }
-            }
-            has_symbol
+            let above: &[u8] = if i > 0 { &input[i - 1][l..=r] } else { &[] };
+            let below: &[u8] = if i + 1 < m { &input[i + 1][l..=r] } else { &[] };
+            has_symbol || above.iter().chain(below).any(|&c| c != b'.')
         })
         .map(|n| n.value)
         .sum()
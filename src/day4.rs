@@ -1,13 +1,53 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
+use std::env;
 
-struct Game {
-    win: HashSet<i64>,
+use num_bigint::BigUint;
+
+// Card numbers are small, so they are kept as bits of a u128. Values that do
+// not fit fall back to a plain list.
+#[derive(Default)]
+struct NumberSet {
+    bits: u128,
+    rest: Vec<i64>,
+}
+
+impl NumberSet {
+    fn insert(&mut self, x: i64) {
+        if (0..128).contains(&x) {
+            self.bits |= 1 << x;
+        } else if !self.rest.contains(&x) {
+            self.rest.push(x);
+        }
+    }
+
+    fn contains(&self, x: &i64) -> bool {
+        if (0..128).contains(x) {
+            self.bits & (1 << x) != 0
+        } else {
+            self.rest.contains(x)
+        }
+    }
+}
+
+impl FromIterator<i64> for NumberSet {
+    fn from_iter<T: IntoIterator<Item = i64>>(iter: T) -> Self {
+        let mut set = NumberSet::default();
+        for x in iter {
+            set.insert(x);
+        }
+        set
+    }
+}
+
+// Your numbers stay a list: a number listed twice matches twice.
+struct Game<S = NumberSet> {
+    win: S,
     your: Vec<i64>,
 }
 
-fn parse_game(line: &str) -> Game {
+fn parse_game<S: FromIterator<i64>>(line: &str) -> Game<S> {
     let src = line.split(": ").nth(1).unwrap();
     let parts: Vec<&str> = src.split(" | ").collect();
     Game {
@@ -27,40 +67,366 @@ fn parse(input: &str) -> Vec<Game> {
     input.lines().map(parse_game).collect()
 }
 
-fn score_part1(cnt: usize) -> i64 {
-    if cnt == 0 {
-        0
-    } else {
-        1 << (cnt - 1)
+#[aoc_generator(day4, part1, HashSet)]
+fn parse_hashset(input: &str) -> Vec<Game<HashSet<i64>>> {
+    input.lines().map(parse_game).collect()
+}
+
+pub trait ScoringRule {
+    fn score(&self, matches: usize) -> i64;
+}
+
+// The puzzle rule: one point for the first match, doubled for every other.
+pub struct Doubling;
+
+pub struct Linear;
+
+// Doubling, but never more than the given number of points.
+pub struct Capped(pub i64);
+
+// Doubling, reduced modulo the given number.
+pub struct Modular(pub i64);
+
+impl ScoringRule for Doubling {
+    fn score(&self, matches: usize) -> i64 {
+        if matches == 0 {
+            0
+        } else {
+            1 << (matches - 1)
+        }
+    }
+}
+
+impl ScoringRule for Linear {
+    fn score(&self, matches: usize) -> i64 {
+        matches as i64
+    }
+}
+
+impl ScoringRule for Capped {
+    fn score(&self, matches: usize) -> i64 {
+        if matches == 0 {
+            0
+        } else {
+            2_i64
+                .checked_pow(matches as u32 - 1)
+                .map_or(self.0, |s| s.min(self.0))
+        }
+    }
+}
+
+impl ScoringRule for Modular {
+    fn score(&self, matches: usize) -> i64 {
+        if matches == 0 {
+            0
+        } else {
+            (1..matches).fold(1 % self.0, |s, _| s * 2 % self.0)
+        }
+    }
+}
+
+// Parses "doubling", "linear", "capped:<max>" or "mod:<m>".
+pub fn scoring_rule(spec: &str) -> Box<dyn ScoringRule> {
+    let (name, arg) = match spec.split_once(':') {
+        Some((name, arg)) => (name, Some(arg.parse().unwrap())),
+        None => (spec, None),
+    };
+    match (name, arg) {
+        ("doubling", None) => Box::new(Doubling),
+        ("linear", None) => Box::new(Linear),
+        ("capped", Some(cap)) => Box::new(Capped(cap)),
+        ("mod", Some(m)) if m > 0 => Box::new(Modular(m)),
+        _ => panic!("Wrong scoring rule: {spec}"),
     }
 }
 
+// A card with `n` matches wins `multiplier` copies of each of the next
+// `n * window` cards. The puzzle rule is a window and multiplier of 1.
+pub struct Propagation {
+    pub window: usize,
+    pub multiplier: usize,
+}
+
+impl Default for Propagation {
+    fn default() -> Self {
+        Propagation {
+            window: 1,
+            multiplier: 1,
+        }
+    }
+}
+
+// Parses "<window>:<multiplier>".
+pub fn propagation(spec: &str) -> Propagation {
+    let (window, multiplier) = spec
+        .split_once(':')
+        .unwrap_or_else(|| panic!("Wrong propagation rule: {spec}"));
+    Propagation {
+        window: window.parse().unwrap(),
+        multiplier: multiplier.parse().unwrap(),
+    }
+}
+
+fn matching(game: &Game) -> usize {
+    game.your.iter().filter(|x| game.win.contains(x)).count()
+}
+
 #[aoc(day4, part1)]
 fn part1(input: &[Game]) -> i64 {
-    let mut sum = 0;
-    for game in input {
-        let matching = game.your.iter().filter(|x| game.win.contains(x)).count();
-        sum += score_part1(matching);
+    input
+        .iter()
+        .map(|game| Doubling.score(matching(game)))
+        .sum()
+}
+
+// Scoring rule taken from AOC_DAY4_SCORING, e.g. `AOC_DAY4_SCORING=capped:8`.
+#[aoc(day4, part1, Rule)]
+fn part1_rule(input: &[Game]) -> i64 {
+    let rule = scoring_rule(&env::var("AOC_DAY4_SCORING").unwrap_or("doubling".to_string()));
+    input.iter().map(|game| rule.score(matching(game))).sum()
+}
+
+// Kept to benchmark the bitset against: `cargo aoc bench -d 4 -p 1`.
+#[aoc(day4, part1, HashSet)]
+fn part1_hashset(input: &[Game<HashSet<i64>>]) -> i64 {
+    input
+        .iter()
+        .map(|game| Doubling.score(game.your.iter().filter(|x| game.win.contains(x)).count()))
+        .sum()
+}
+
+// Arithmetic used to count copies. Counts grow exponentially on long
+// cascades, so besides plain `usize` they can be kept exact or modulo `m`.
+pub trait Counting {
+    type Value: Clone;
+
+    fn count(&self, x: usize) -> Self::Value;
+    fn add(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+    fn sub(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+    fn mul(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+}
+
+pub struct Native;
+
+pub struct Exact;
+
+// Any modulus works, the cascade never divides.
+pub struct Modulo(pub u64);
+
+impl Counting for Native {
+    type Value = usize;
+
+    fn count(&self, x: usize) -> usize {
+        x
+    }
+
+    fn add(&self, a: &usize, b: &usize) -> usize {
+        a + b
+    }
+
+    fn sub(&self, a: &usize, b: &usize) -> usize {
+        a - b
+    }
+
+    fn mul(&self, a: &usize, b: &usize) -> usize {
+        a * b
     }
-    sum
 }
 
-#[aoc(day4, part2)]
-fn part2(input: &[Game]) -> i64 {
-    let mut m: HashMap<usize, usize> = HashMap::new();
-    for (i, game) in input.iter().enumerate() {
-        let matching = game.your.iter().filter(|x| game.win.contains(x)).count();
-        let card = i + 1;
-        let cnt = {
-            let _cnt = m.entry(card).or_default();
-            *_cnt += 1;
-            *_cnt
-        };
-        for j in 1..=matching {
-            *m.entry(card + j).or_default() += cnt;
+impl Counting for Exact {
+    type Value = BigUint;
+
+    fn count(&self, x: usize) -> BigUint {
+        BigUint::from(x)
+    }
+
+    fn add(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a + b
+    }
+
+    fn sub(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a - b
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a * b
+    }
+}
+
+impl Counting for Modulo {
+    type Value = u64;
+
+    fn count(&self, x: usize) -> u64 {
+        (x as u64) % self.0
+    }
+
+    fn add(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 + *b as u128) % self.0 as u128) as u64
+    }
+
+    fn sub(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 + self.0 as u128 - *b as u128) % self.0 as u128) as u64
+    }
+
+    fn mul(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 * *b as u128) % self.0 as u128) as u64
+    }
+}
+
+pub struct Cascade<T = usize> {
+    // Number of instances held of each card, originals included.
+    pub copies: Vec<T>,
+    // Number of cards each original card accounts for: itself plus every
+    // copy it spawns down the cascade. Sums to the same total as `copies`.
+    pub yields: Vec<T>,
+    // Copies that would have been won of cards past the end of the table.
+    pub overflow: T,
+    pub total: T,
+    // Sum of `yields` from each card to the end.
+    suffix: Vec<T>,
+}
+
+pub fn cascade(matches: &[usize], rule: &Propagation) -> Cascade {
+    cascade_with(matches, rule, &Native)
+}
+
+pub fn cascade_with<C: Counting>(
+    matches: &[usize],
+    rule: &Propagation,
+    counting: &C,
+) -> Cascade<C::Value> {
+    let n = matches.len();
+    let span = |i: usize, m: usize| i + 1 + m * rule.window;
+    let zero = counting.count(0);
+    let one = counting.count(1);
+    let multiplier = counting.count(rule.multiplier);
+
+    let mut copies = Vec::with_capacity(n);
+    let mut expire = vec![zero.clone(); n + 1];
+    let mut running = zero.clone();
+    let mut overflow = zero.clone();
+    let mut total = zero.clone();
+    for (i, &m) in matches.iter().enumerate() {
+        running = counting.sub(&running, &expire[i]);
+        let held = counting.add(&one, &running);
+        let won = counting.mul(&held, &multiplier);
+        let end = span(i, m);
+        if end > n {
+            let lost = counting.mul(&won, &counting.count(end - n));
+            overflow = counting.add(&overflow, &lost);
         }
+        running = counting.add(&running, &won);
+        expire[end.min(n)] = counting.add(&expire[end.min(n)], &won);
+        total = counting.add(&total, &held);
+        copies.push(held);
+    }
+
+    let mut yields = vec![zero.clone(); n];
+    let mut suffix = vec![zero; n + 1];
+    for (i, &m) in matches.iter().enumerate().rev() {
+        let end = span(i, m).min(n);
+        let spawned = counting.sub(&suffix[i + 1], &suffix[end]);
+        yields[i] = counting.add(&one, &counting.mul(&multiplier, &spawned));
+        suffix[i] = counting.add(&yields[i], &suffix[i + 1]);
+    }
+
+    Cascade {
+        copies,
+        yields,
+        overflow,
+        total,
+        suffix,
     }
-    m.values().sum::<usize>() as i64
+}
+
+// Answers "what if" questions about a single card in O(1) each. Every one of
+// the `copies[k]` instances of card k accounts for `yields[k]` cards, and
+// `yields[k]` only depends on the cards after k, so changing card k shifts the
+// total by `copies[k]` times the change in its yield.
+pub struct WhatIf<'a> {
+    matches: &'a [usize],
+    rule: &'a Propagation,
+    pub cascade: Cascade,
+}
+
+impl<'a> WhatIf<'a> {
+    pub fn new(matches: &'a [usize], rule: &'a Propagation) -> Self {
+        WhatIf {
+            matches,
+            rule,
+            cascade: cascade(matches, rule),
+        }
+    }
+
+    fn total_with_yield(&self, k: usize, yields: usize) -> usize {
+        let copies = self.cascade.copies[k];
+        self.cascade.total - copies * self.cascade.yields[k] + copies * yields
+    }
+
+    // Total if card `k` (0-based) is taken out. The other cards keep their
+    // numbers, so copies of card k that would have been won are just lost.
+    pub fn total_without(&self, k: usize) -> usize {
+        self.total_with_yield(k, 0)
+    }
+
+    // Total if card `k` (0-based) had `matches` matching numbers instead.
+    pub fn total_with_matches(&self, k: usize, matches: usize) -> usize {
+        let n = self.matches.len();
+        let end = (k + 1 + matches * self.rule.window).min(n);
+        let suffix = &self.cascade.suffix;
+        self.total_with_yield(k, 1 + self.rule.multiplier * (suffix[k + 1] - suffix[end]))
+    }
+
+    // Card-copy dependency DAG in DOT format, with an edge from every card to
+    // each card it wins copies of.
+    pub fn dot(&self) -> String {
+        let n = self.matches.len();
+        let mut out = String::from("digraph cards {\n");
+        for (i, &m) in self.matches.iter().enumerate() {
+            out += &format!(
+                "    {} [label=\"Card {}\\n{} copies\"];\n",
+                i + 1,
+                i + 1,
+                self.cascade.copies[i]
+            );
+            for j in i + 1..(i + 1 + m * self.rule.window).min(n) {
+                out += &format!("    {} -> {};\n", i + 1, j + 1);
+            }
+        }
+        out += "}\n";
+        out
+    }
+}
+
+#[aoc(day4, part2)]
+fn part2(input: &[Game]) -> i64 {
+    let matches: Vec<usize> = input.iter().map(matching).collect();
+    cascade(&matches, &Propagation::default()).total as i64
+}
+
+// Copy rule taken from AOC_DAY4_PROPAGATION, e.g. `AOC_DAY4_PROPAGATION=2:3`.
+#[aoc(day4, part2, Rule)]
+fn part2_rule(input: &[Game]) -> i64 {
+    let rule = env::var("AOC_DAY4_PROPAGATION")
+        .map_or_else(|_| Propagation::default(), |s| propagation(&s));
+    let matches: Vec<usize> = input.iter().map(matching).collect();
+    cascade(&matches, &rule).total as i64
+}
+
+#[aoc(day4, part2, Exact)]
+fn part2_exact(input: &[Game]) -> String {
+    let matches: Vec<usize> = input.iter().map(matching).collect();
+    cascade_with(&matches, &Propagation::default(), &Exact)
+        .total
+        .to_string()
+}
+
+// Modulus taken from AOC_DAY4_MODULUS, 1_000_000_007 by default.
+#[aoc(day4, part2, Modulo)]
+fn part2_modulo(input: &[Game]) -> u64 {
+    let modulus = env::var("AOC_DAY4_MODULUS").map_or(1_000_000_007, |m| m.parse().unwrap());
+    let matches: Vec<usize> = input.iter().map(matching).collect();
+    cascade_with(&matches, &Propagation::default(), &Modulo(modulus)).total
 }
 
 #[cfg(test)]
@@ -71,6 +437,20 @@ mod tests {
     fn part1_example() {
         let example = include_str!("examples/day04.txt");
         assert_eq!(part1(&parse(example)), 13);
+        assert_eq!(part1_hashset(&parse_hashset(example)), 13);
+    }
+
+    #[test]
+    fn number_set_fallback() {
+        let win: NumberSet = [1, 127, 128, -5, 1000].into_iter().collect();
+        let found = [127, 128, 1000, 2000, 3, -5]
+            .iter()
+            .filter(|x| win.contains(x));
+        assert_eq!(found.count(), 4);
+
+        let repeated = "Card 1: 1 2 200 | 1 1 200 200 3";
+        assert_eq!(matching(&parse_game(repeated)), 4);
+        assert_eq!(part1_hashset(&parse_hashset(repeated)), 8);
     }
 
     #[test]
@@ -78,4 +458,79 @@ mod tests {
         let example = include_str!("examples/day04.txt");
         assert_eq!(part2(&parse(example)), 30);
     }
+
+    #[test]
+    fn cascade_example() {
+        let example = include_str!("examples/day04.txt");
+        let matches: Vec<usize> = parse(example).iter().map(matching).collect();
+        let cascade = cascade(&matches, &Propagation::default());
+        assert_eq!(cascade.copies, vec![1, 2, 4, 8, 14, 1]);
+        assert_eq!(cascade.yields, vec![15, 7, 4, 2, 1, 1]);
+        assert_eq!(cascade.yields.iter().sum::<usize>(), cascade.total);
+        assert_eq!(cascade.overflow, 0);
+        assert_eq!(super::cascade(&[0, 3], &Propagation::default()).overflow, 3);
+    }
+
+    #[test]
+    fn scoring_rules() {
+        let scores = |spec: &str| {
+            (0..=5)
+                .map(|m| scoring_rule(spec).score(m))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(scores("doubling"), vec![0, 1, 2, 4, 8, 16]);
+        assert_eq!(scores("linear"), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(scores("capped:5"), vec![0, 1, 2, 4, 5, 5]);
+        assert_eq!(scores("mod:5"), vec![0, 1, 2, 4, 3, 1]);
+        assert_eq!(Capped(10).score(100), 10);
+
+        let rule = propagation("2:3");
+        let cascade = cascade(&[1, 0, 0, 0], &rule);
+        assert_eq!(cascade.copies, vec![1, 4, 4, 1]);
+        assert_eq!(cascade.yields.iter().sum::<usize>(), cascade.total);
+    }
+
+    #[test]
+    fn cascade_big_counts() {
+        // Every card wins a copy of each card after it, so card i is held
+        // 2^i times and the total is 2^n - 1.
+        let matches: Vec<usize> = (0..100).rev().collect();
+        let rule = Propagation::default();
+
+        let exact = cascade_with(&matches, &rule, &Exact);
+        assert_eq!(exact.total, (BigUint::from(1_u8) << 100) - 1_u8);
+        assert_eq!(exact.copies[99], BigUint::from(1_u8) << 99);
+
+        let modulo = cascade_with(&matches, &rule, &Modulo(1_000_000_007));
+        assert_eq!(
+            modulo.total,
+            (exact.total % 1_000_000_007_u64).to_u64_digits()[0]
+        );
+        assert_eq!(modulo.copies[10], 1024);
+    }
+
+    #[test]
+    fn what_if() {
+        let example = include_str!("examples/day04.txt");
+        let matches: Vec<usize> = parse(example).iter().map(matching).collect();
+        let rule = Propagation::default();
+        let what_if = WhatIf::new(&matches, &rule);
+
+        for k in 0..matches.len() {
+            for m in 0..=4 {
+                let mut changed = matches.clone();
+                changed[k] = m;
+                let expected = cascade(&changed, &rule).total;
+                assert_eq!(what_if.total_with_matches(k, m), expected);
+            }
+        }
+        assert_eq!(what_if.total_without(0), 15);
+        assert_eq!(what_if.total_without(5), 29);
+
+        let dot = WhatIf::new(&[1, 0], &rule).dot();
+        assert_eq!(
+            dot,
+            "digraph cards {\n    1 [label=\"Card 1\\n1 copies\"];\n    1 -> 2;\n    2 [label=\"Card 2\\n2 copies\"];\n}\n"
+        );
+    }
 }
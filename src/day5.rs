@@ -2,27 +2,246 @@ use aoc_runner_derive::{aoc, aoc_generator};
 
 use itertools::Itertools;
 
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+// Values cover all of u64. The end of a half-open interval can be 2^64
+// itself, so interval ends and offsets between values are kept in 128 bits.
+const DOMAIN_END: u128 = 1 << 64;
+
+type Segment = (u128, u128);
+
 #[derive(Debug)]
 struct Range {
-    src: i64,
-    dst: i64,
-    len: i64,
+    src: u64,
+    dst: u64,
+    len: u64,
+    line: usize,
+}
+
+impl Range {
+    fn src_end(&self) -> u128 {
+        self.src as u128 + self.len as u128
+    }
+
+    fn offset(&self) -> i128 {
+        self.dst as i128 - self.src as i128
+    }
 }
 
 type Mapping = Vec<Range>;
 
 #[derive(Debug)]
-struct Input {
-    seeds: Vec<i64>,
+pub struct Input {
+    seeds: Vec<u64>,
+    // Maps in file order, with the `(source, destination)` categories from
+    // their headers at the same index.
     mappings: Vec<Mapping>,
+    categories: Vec<(String, String)>,
+    // Indices of the maps leading from "seed" to "location".
+    chain: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AlmanacError {
+    UnknownCategory(String),
+    MissingLink(String, String),
+    Cycle(String),
+    Ranges(Vec<RangeIssue>),
+    // A range or seed range on the given line runs past 2^64.
+    OutOfDomain(usize),
+}
+
+// Problems in a single map, with 1-based line numbers of the ranges involved.
+#[derive(Debug, PartialEq)]
+pub enum RangeIssue {
+    ZeroLength(usize),
+    OverlappingSource(usize, usize),
+    DestinationCollision(usize, usize),
+}
+
+impl fmt::Display for RangeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RangeIssue::ZeroLength(l) => write!(f, "line {l}: zero-length range"),
+            RangeIssue::OverlappingSource(a, b) => {
+                write!(f, "lines {a} and {b}: overlapping source ranges")
+            }
+            RangeIssue::DestinationCollision(a, b) => {
+                write!(f, "lines {a} and {b}: overlapping destination ranges")
+            }
+        }
+    }
+}
+
+// Every pair of ranges that overlap on `key`, earlier-starting one first.
+fn overlaps(mapping: &Mapping, key: fn(&Range) -> u64) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<&Range> = mapping.iter().filter(|r| r.len > 0).collect();
+    ranges.sort_by_key(|r| key(r));
+    let mut res = Vec::new();
+    // Earlier ranges that reach past the start of the current one.
+    let mut active: Vec<&Range> = Vec::new();
+    let end = |r: &Range| key(r) as u128 + r.len as u128;
+    for range in ranges {
+        active.retain(|prev| end(prev) > key(range) as u128);
+        res.extend(active.iter().map(|prev| (prev.line, range.line)));
+        active.push(range);
+    }
+    res
+}
+
+fn check_ranges(mapping: &Mapping) -> Vec<RangeIssue> {
+    let mut issues: Vec<RangeIssue> = mapping
+        .iter()
+        .filter(|r| r.len == 0)
+        .map(|r| RangeIssue::ZeroLength(r.line))
+        .collect();
+    issues.extend(
+        overlaps(mapping, |r| r.src)
+            .into_iter()
+            .map(|(a, b)| RangeIssue::OverlappingSource(a, b)),
+    );
+    issues.extend(
+        overlaps(mapping, |r| r.dst)
+            .into_iter()
+            .map(|(a, b)| RangeIssue::DestinationCollision(a, b)),
+    );
+    issues
+}
+
+impl fmt::Display for AlmanacError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlmanacError::UnknownCategory(c) => write!(f, "unknown category {c}"),
+            AlmanacError::MissingLink(from, to) => write!(f, "no maps lead from {from} to {to}"),
+            AlmanacError::Cycle(c) => write!(f, "maps form a cycle through {c}"),
+            AlmanacError::Ranges(issues) => write!(f, "{}", issues.iter().join(", ")),
+            AlmanacError::OutOfDomain(l) => write!(f, "line {l}: range runs past 2^64"),
+        }
+    }
+}
+
+impl Error for AlmanacError {}
+
+impl Input {
+    fn layers(&self) -> impl Iterator<Item = &Mapping> + '_ {
+        self.chain.iter().map(|&i| &self.mappings[i])
+    }
+
+    // Indices of the maps leading from one category to another, shortest first.
+    pub fn path(&self, from: &str, to: &str) -> Result<Vec<usize>, AlmanacError> {
+        for c in [from, to] {
+            if !self.categories.iter().any(|(s, d)| s == c || d == c) {
+                return Err(AlmanacError::UnknownCategory(c.to_string()));
+            }
+        }
+        let mut prev: HashMap<&str, usize> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(cur) = queue.pop_front() {
+            if cur == to {
+                let mut path = Vec::new();
+                let mut cur = to;
+                while cur != from {
+                    let i = prev[cur];
+                    path.push(i);
+                    cur = &self.categories[i].0;
+                }
+                path.reverse();
+                return Ok(path);
+            }
+            for (i, (src, dst)) in self.categories.iter().enumerate() {
+                if src == cur && dst != from && !prev.contains_key(dst.as_str()) {
+                    prev.insert(dst, i);
+                    queue.push_back(dst);
+                }
+            }
+        }
+        Err(AlmanacError::MissingLink(from.to_string(), to.to_string()))
+    }
+
+    pub fn convert(&self, from: &str, to: &str, value: u64) -> Result<u64, AlmanacError> {
+        Ok(self
+            .path(from, to)?
+            .into_iter()
+            .fold(value, |x, i| resolve_map(&self.mappings[i], x)))
+    }
+
+    pub fn range_issues(&self) -> Vec<RangeIssue> {
+        self.mappings.iter().flat_map(check_ranges).collect()
+    }
+
+    pub fn validate(&self) -> Result<(), AlmanacError> {
+        fn visit<'a>(
+            input: &'a Input,
+            cur: &'a str,
+            active: &mut Vec<&'a str>,
+            done: &mut HashSet<&'a str>,
+        ) -> Result<(), AlmanacError> {
+            if active.contains(&cur) {
+                return Err(AlmanacError::Cycle(cur.to_string()));
+            }
+            if done.insert(cur) {
+                active.push(cur);
+                for (src, dst) in &input.categories {
+                    if src == cur {
+                        visit(input, dst, active, done)?;
+                    }
+                }
+                active.pop();
+            }
+            Ok(())
+        }
+
+        let mut done = HashSet::new();
+        for (src, _) in &self.categories {
+            visit(self, src, &mut Vec::new(), &mut done)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_header(header: &str) -> (String, String) {
+    header
+        .strip_suffix(" map:")
+        .and_then(|h| h.split_once("-to-"))
+        .map(|(src, dst)| (src.to_string(), dst.to_string()))
+        .unwrap_or_else(|| panic!("Wrong header: {header}"))
 }
 
 #[aoc_generator(day5)]
-fn parse(input: &str) -> Input {
-    let mut lines = input.lines();
-    let seeds: Vec<i64> = lines
+pub fn parse(input: &str) -> Input {
+    parse_almanac(input).unwrap_or_else(|e| panic!("Wrong almanac: {e}"))
+}
+
+// Like `parse_almanac`, but refuses maps whose ranges overlap, collide or
+// are empty, as those make the answer depend on the order of the ranges.
+pub fn parse_strict(input: &str) -> Result<Input, AlmanacError> {
+    let input = parse_almanac(input)?;
+    let issues = input.range_issues();
+    if issues.is_empty() {
+        Ok(input)
+    } else {
+        Err(AlmanacError::Ranges(issues))
+    }
+}
+
+#[aoc_generator(day5, part1, Strict)]
+fn parse_strict_part1(input: &str) -> Result<Input, AlmanacError> {
+    parse_strict(input)
+}
+
+#[aoc_generator(day5, part2, Strict)]
+fn parse_strict_part2(input: &str) -> Result<Input, AlmanacError> {
+    parse_strict(input)
+}
+
+pub fn parse_almanac(input: &str) -> Result<Input, AlmanacError> {
+    let mut lines = input.lines().enumerate().map(|(i, l)| (i + 1, l));
+    let seeds: Vec<u64> = lines
         .next()
         .unwrap()
+        .1
         .split(": ")
         .nth(1)
         .unwrap()
@@ -33,45 +252,64 @@ fn parse(input: &str) -> Input {
     lines.next();
 
     let mut mappings = Vec::new();
+    let mut categories = Vec::new();
 
-    loop {
-        if lines.next().is_some() {
-            let mut mapping = Mapping::new();
-            loop {
-                match lines.next() {
-                    None | Some("") => {
-                        break;
-                    }
-                    Some(range) => {
-                        let parts: Vec<i64> =
-                            range.split(' ').map(|x| x.parse().unwrap()).collect();
-                        mapping.push(Range {
-                            src: parts[1],
-                            dst: parts[0],
-                            len: parts[2],
-                        })
+    while let Some((_, header)) = lines.next() {
+        categories.push(parse_header(header));
+        let mut mapping = Mapping::new();
+        loop {
+            match lines.next() {
+                None | Some((_, "")) => {
+                    break;
+                }
+                Some((line, range)) => {
+                    let parts: Vec<u64> = range.split(' ').map(|x| x.parse().unwrap()).collect();
+                    let range = Range {
+                        src: parts[1],
+                        dst: parts[0],
+                        len: parts[2],
+                        line,
+                    };
+                    if range.src_end() > DOMAIN_END
+                        || range.dst as u128 + range.len as u128 > DOMAIN_END
+                    {
+                        return Err(AlmanacError::OutOfDomain(line));
                     }
+                    mapping.push(range);
                 }
             }
-            mapping.sort_by(|a, b| a.src.cmp(&b.src));
-            mappings.push(mapping);
-        } else {
-            break;
         }
+        mapping.sort_by_key(|a| a.src);
+        mappings.push(mapping);
+    }
+
+    if seeds
+        .chunks(2)
+        .any(|c| c.len() == 2 && c[0] as u128 + c[1] as u128 > DOMAIN_END)
+    {
+        return Err(AlmanacError::OutOfDomain(1));
     }
 
-    Input { seeds, mappings }
+    let mut input = Input {
+        seeds,
+        mappings,
+        categories,
+        chain: Vec::new(),
+    };
+    input.validate()?;
+    input.chain = input.path("seed", "location")?;
+    Ok(input)
 }
 
-fn map_range(range: &Range, src: i64) -> Option<i64> {
-    if (range.src..range.src + range.len).contains(&src) {
-        Some(range.dst + src - range.src)
+fn map_range(range: &Range, src: u64) -> Option<u64> {
+    if src >= range.src && src - range.src < range.len {
+        Some(range.dst + (src - range.src))
     } else {
         None
     }
 }
 
-fn resolve_map(mapping: &Mapping, src: i64) -> i64 {
+fn resolve_map(mapping: &Mapping, src: u64) -> u64 {
     for range in mapping {
         if let Some(dst) = map_range(range, src) {
             return dst;
@@ -80,16 +318,190 @@ fn resolve_map(mapping: &Mapping, src: i64) -> i64 {
     src
 }
 
-fn map_seed(input: &Input, seed: i64) -> i64 {
+fn map_seed(input: &Input, seed: u64) -> u64 {
     let mut dst = seed;
-    for map in &input.mappings {
+    for map in input.layers() {
         dst = resolve_map(map, dst);
     }
     dst
 }
 
+// A piecewise-linear function over u64: piece `i` maps
+// `[pieces[i].start, pieces[i + 1].start)` to itself shifted by `offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Piece {
+    start: u64,
+    offset: i128,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalMap {
+    pieces: Vec<Piece>,
+}
+
+impl IntervalMap {
+    fn identity() -> Self {
+        IntervalMap {
+            pieces: vec![Piece {
+                start: 0,
+                offset: 0,
+            }],
+        }
+    }
+
+    // Like `resolve_map`, the first range containing a value wins, so a
+    // range only gets the part of its source past every earlier range.
+    fn from_mapping(mapping: &Mapping) -> Self {
+        let mut pieces = Vec::new();
+        let mut cur = 0;
+        for range in mapping {
+            if range.src as u128 > cur {
+                pieces.push(Piece {
+                    start: cur as u64,
+                    offset: 0,
+                });
+            }
+            let start = cur.max(range.src as u128);
+            if start < range.src_end() {
+                pieces.push(Piece {
+                    start: start as u64,
+                    offset: range.offset(),
+                });
+                cur = range.src_end();
+            }
+        }
+        if cur < DOMAIN_END {
+            pieces.push(Piece {
+                start: cur as u64,
+                offset: 0,
+            });
+        }
+        IntervalMap::normalized(pieces)
+    }
+
+    // Drops empty pieces and merges neighbours with the same offset.
+    fn normalized(pieces: Vec<Piece>) -> Self {
+        let mut res: Vec<Piece> = Vec::with_capacity(pieces.len());
+        for piece in pieces {
+            match res.last_mut() {
+                Some(last) if last.start == piece.start => *last = piece,
+                _ => res.push(piece),
+            }
+            if let [.., a, b] = res[..] {
+                if a.offset == b.offset {
+                    res.pop();
+                }
+            }
+        }
+        IntervalMap { pieces: res }
+    }
+
+    fn end(&self, i: usize) -> u128 {
+        self.pieces
+            .get(i + 1)
+            .map_or(DOMAIN_END, |p| p.start as u128)
+    }
+
+    fn index(&self, x: u64) -> usize {
+        self.pieces.partition_point(|p| p.start <= x) - 1
+    }
+
+    pub fn get(&self, x: u64) -> u64 {
+        (x as i128 + self.pieces[self.index(x)].offset) as u64
+    }
+
+    // Points where the function jumps, i.e. every piece start but the first.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u64> + '_ {
+        self.pieces.iter().skip(1).map(|p| p.start)
+    }
+
+    // Applies `self` first, then `next`.
+    pub fn then(&self, next: &IntervalMap) -> IntervalMap {
+        let mut pieces = Vec::new();
+        for (i, piece) in self.pieces.iter().enumerate() {
+            let end = self.end(i) as i128 + piece.offset;
+            let mut j = next.index((piece.start as i128 + piece.offset) as u64);
+            while j < next.pieces.len() && (next.pieces[j].start as i128) < end {
+                let start = next.pieces[j].start as i128 - piece.offset;
+                pieces.push(Piece {
+                    start: piece.start.max(start.max(0) as u64),
+                    offset: piece.offset + next.pieces[j].offset,
+                });
+                j += 1;
+            }
+        }
+        IntervalMap::normalized(pieces)
+    }
+}
+
+impl IntervalMap {
+    // Sorted, merged intervals of every `x` with `self.get(x)` in `[start, end)`.
+    pub fn preimage(&self, (start, end): Segment) -> Vec<Segment> {
+        let res: Vec<Segment> = self
+            .pieces
+            .iter()
+            .enumerate()
+            .map(|(i, piece)| {
+                (
+                    (piece.start as i128).max(start as i128 - piece.offset),
+                    (self.end(i) as i128).min(end as i128 - piece.offset),
+                )
+            })
+            .filter(|(s, e)| s < e)
+            .map(|(s, e)| (s as u128, e as u128))
+            .collect();
+        normalize(res)
+    }
+}
+
+// Seed intervals that end up in the location interval `[start, end)`.
+pub fn seeds_for_locations(input: &Input, locations: Segment) -> Vec<Segment> {
+    let seeds = compose(input).preimage(locations);
+    for &(s, e) in &seeds {
+        debug_assert!([s, e - 1]
+            .iter()
+            .all(|&x| (locations.0..locations.1).contains(&(map_seed(input, x as u64) as u128))));
+    }
+    seeds
+}
+
+fn compose(input: &Input) -> IntervalMap {
+    input
+        .layers()
+        .fold(IntervalMap::identity(), |acc, mapping| {
+            acc.then(&IntervalMap::from_mapping(mapping))
+        })
+}
+
+fn seed_segments(input: &Input) -> Vec<Segment> {
+    input
+        .seeds
+        .iter()
+        .copied()
+        .chunks(2)
+        .into_iter()
+        .map(|mut chunk| {
+            let start = chunk.next().unwrap() as u128;
+            let len = chunk.next().unwrap() as u128;
+            (start, start + len)
+        })
+        .collect()
+}
+
 #[aoc(day5, part1)]
-fn part1(input: &Input) -> i64 {
+fn part1(input: &Input) -> u64 {
+    let map = compose(input);
+    input.seeds.iter().map(|&s| map.get(s)).min().unwrap()
+}
+
+// Refuses almanacs with range issues instead of answering, see `parse_strict`.
+#[aoc(day5, part1, Strict)]
+fn part1_strict(input: &Input) -> u64 {
+    part1(input)
+}
+
+#[aoc(day5, part1, Layered)]
+fn part1_layered(input: &Input) -> u64 {
     input
         .seeds
         .iter()
@@ -98,20 +510,37 @@ fn part1(input: &Input) -> i64 {
         .unwrap()
 }
 
-fn process_segment((mut start, end): (i64, i64), mapping: &Mapping) -> Vec<(i64, i64)> {
+// Sorts segments and merges the ones that overlap or touch.
+fn normalize(mut segments: Vec<Segment>) -> Vec<Segment> {
+    segments.sort();
+    segments.into_iter().fold(Vec::new(), |mut acc, (s, e)| {
+        match acc.last_mut() {
+            Some(last) if last.1 >= s => last.1 = last.1.max(e),
+            _ => acc.push((s, e)),
+        }
+        acc
+    })
+}
+
+fn covered(segments: &[Segment]) -> u128 {
+    segments.iter().map(|(s, e)| e - s).sum()
+}
+
+fn process_segment((mut start, end): Segment, mapping: &Mapping) -> Vec<Segment> {
     let mut res = Vec::new();
     for range in mapping {
-        if start < range.src {
-            let consume = (end - start).min(range.src - start);
+        let (src, dst, len) = (range.src as u128, range.dst as u128, range.len as u128);
+        if start < src {
+            let consume = (end - start).min(src - start);
             if consume > 0 {
                 res.push((start, start + consume));
                 start += consume;
             }
         }
-        let skip = (start - range.src).min(range.len);
-        let consume = (end - start).min(range.len - skip);
+        let skip = start.saturating_sub(src).min(len);
+        let consume = (end - start).min(len - skip);
         if consume > 0 {
-            res.push((range.dst + skip, range.dst + skip + consume));
+            res.push((dst + skip, dst + skip + consume));
             start += consume;
         }
     }
@@ -121,28 +550,279 @@ fn process_segment((mut start, end): (i64, i64), mapping: &Mapping) -> Vec<(i64,
     res
 }
 
+// Every piece of the composed map is increasing, so the minimum over a
+// segment is at its start or at one of the breakpoints inside it.
 #[aoc(day5, part2)]
-fn part2(input: &Input) -> i64 {
-    let mut segments: Vec<(i64, i64)> = input
-        .seeds
-        .iter()
-        .copied()
-        .chunks(2)
+fn part2(input: &Input) -> u64 {
+    let map = compose(input);
+    let breakpoints: Vec<u64> = map.breakpoints().collect();
+    seed_segments(input)
         .into_iter()
-        .map(|mut chunk| {
-            let start = chunk.next().unwrap();
-            let len = chunk.next().unwrap();
-            (start, start + len)
+        .filter(|(start, end)| start < end)
+        .flat_map(|(start, end)| {
+            let from = breakpoints.partition_point(|&b| b as u128 <= start);
+            let to = breakpoints.partition_point(|&b| (b as u128) < end);
+            std::iter::once(start as u64).chain(breakpoints[from..to].iter().copied())
+        })
+        .map(|x| map.get(x))
+        .min()
+        .unwrap()
+}
+
+// Walks the pieces of the composed map by increasing location and stops as
+// soon as no later piece can beat the best location found.
+#[aoc(day5, part2, Inverse)]
+fn part2_inverse(input: &Input) -> u64 {
+    let map = compose(input);
+    let segments: Vec<Segment> = seed_segments(input)
+        .into_iter()
+        .filter(|(s, e)| s < e)
+        .collect();
+    let mut pieces: Vec<(u128, u128, u128)> = map
+        .pieces
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let start = p.start as u128;
+            ((start as i128 + p.offset) as u128, start, map.end(i))
         })
         .collect();
-    for mapping in &input.mappings {
-        segments = segments
+    pieces.sort();
+
+    let mut best = DOMAIN_END;
+    for (location, start, end) in pieces {
+        if location >= best {
+            break;
+        }
+        for &(s, e) in &segments {
+            if s < end && start < e {
+                best = best.min(location + s.max(start) - start);
+            }
+        }
+    }
+    best as u64
+}
+
+#[aoc(day5, part2, Strict)]
+fn part2_strict(input: &Input) -> u64 {
+    part2(input)
+}
+
+#[aoc(day5, part2, Layered)]
+fn part2_layered(input: &Input) -> u64 {
+    let mut segments = normalize(seed_segments(input));
+    for mapping in input.layers() {
+        segments = normalize(
+            segments
+                .into_iter()
+                .flat_map(|s| process_segment(s, mapping).into_iter())
+                .collect(),
+        );
+    }
+
+    segments.into_iter().map(|(s, _)| s as u64).min().unwrap()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LayerStats {
+    pub category: String,
+    // Segments produced by `process_segment`, and what is left after merging.
+    pub segments: usize,
+    pub merged: usize,
+    // Length covered before and after merging. A map that sends two source
+    // ranges onto overlapping destinations makes `merged_length` smaller.
+    pub length: u128,
+    pub merged_length: u128,
+}
+
+impl LayerStats {
+    // Every seed went somewhere and no two seeds ended up at the same place.
+    pub fn conserved(&self, previous: &LayerStats) -> bool {
+        self.length == previous.merged_length && self.merged_length == self.length
+    }
+}
+
+// Segment statistics after the seeds and after each map of the chain.
+pub fn layer_stats(input: &Input) -> Vec<LayerStats> {
+    let seeds = seed_segments(input);
+    let mut segments = normalize(seeds.clone());
+    let mut stats = vec![LayerStats {
+        category: "seed".to_string(),
+        segments: seeds.len(),
+        merged: segments.len(),
+        length: covered(&seeds),
+        merged_length: covered(&segments),
+    }];
+    for &i in &input.chain {
+        let mapped: Vec<Segment> = segments
             .into_iter()
-            .flat_map(|s| process_segment(s, mapping).into_iter())
+            .flat_map(|s| process_segment(s, &input.mappings[i]).into_iter())
             .collect();
+        let (count, length) = (mapped.len(), covered(&mapped));
+        segments = normalize(mapped);
+        stats.push(LayerStats {
+            category: input.categories[i].1.clone(),
+            segments: count,
+            merged: segments.len(),
+            length,
+            merged_length: covered(&segments),
+        });
+    }
+    stats
+}
+
+// One line per category, flagging layers where seeds went missing or merged.
+#[aoc(day5, part2, Stats)]
+fn part2_stats(input: &Input) -> String {
+    let stats = layer_stats(input);
+    let mut out = String::new();
+    for (i, s) in stats.iter().enumerate() {
+        out += &format!(
+            "{}: {} segments ({} merged), length {} ({} merged){}\n",
+            s.category,
+            s.segments,
+            s.merged,
+            s.length,
+            s.merged_length,
+            if i > 0 && !s.conserved(&stats[i - 1]) {
+                ", not conserved"
+            } else {
+                ""
+            }
+        );
     }
+    out
+}
 
-    segments.into_iter().map(|(s, _)| s).min().unwrap()
+// Part of a segment in one layer and where `process_segment` sent it.
+#[derive(Debug, PartialEq)]
+pub struct Link {
+    pub from: Segment,
+    pub to: Segment,
+}
+
+// Merged segments of one category, and the links from the previous layer.
+#[derive(Debug)]
+pub struct FlowLayer {
+    pub category: String,
+    pub segments: Vec<Segment>,
+    pub links: Vec<Link>,
+}
+
+pub fn flow(input: &Input) -> Vec<FlowLayer> {
+    let mut layers = vec![FlowLayer {
+        category: "seed".to_string(),
+        segments: normalize(seed_segments(input)),
+        links: Vec::new(),
+    }];
+    for &i in &input.chain {
+        let mut links = Vec::new();
+        for &segment in &layers.last().unwrap().segments {
+            // Outputs come in the order their source parts were consumed.
+            let mut cur = segment.0;
+            for to in process_segment(segment, &input.mappings[i]) {
+                let len = to.1 - to.0;
+                links.push(Link {
+                    from: (cur, cur + len),
+                    to,
+                });
+                cur += len;
+            }
+        }
+        layers.push(FlowLayer {
+            category: input.categories[i].1.clone(),
+            segments: normalize(links.iter().map(|l| l.to).collect()),
+            links,
+        });
+    }
+    layers
+}
+
+fn flow_bounds(layers: &[FlowLayer]) -> (u128, u128) {
+    let segments = || layers.iter().flat_map(|l| l.segments.iter());
+    let lo = segments().map(|s| s.0).min().unwrap_or(0);
+    let hi = segments().map(|s| s.1).max().unwrap_or(1);
+    (lo, hi.max(lo + 1))
+}
+
+// One row per category, with `#` over the values its segments cover.
+pub fn flow_ascii(layers: &[FlowLayer], width: usize) -> String {
+    let (lo, hi) = flow_bounds(layers);
+    let col = |v: u128| ((v - lo) as f64 / (hi - lo) as f64 * width as f64) as usize;
+    let label = layers.iter().map(|l| l.category.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for layer in layers {
+        let mut row = vec![' '; width];
+        for &(s, e) in &layer.segments {
+            let first = col(s).min(width - 1);
+            for c in &mut row[first..col(e).clamp(first + 1, width)] {
+                *c = '#';
+            }
+        }
+        out += &format!(
+            "{:>label$} |{}|\n",
+            layer.category,
+            row.iter().collect::<String>()
+        );
+    }
+    out += &format!("{:>label$}  {lo}..{hi}\n", "");
+    out
+}
+
+// Sankey-style SVG: a column of segments per category, with a band from
+// every segment part to where it was mapped in the next column.
+pub fn flow_svg(layers: &[FlowLayer]) -> String {
+    const COLUMN: f64 = 160.0;
+    const BAR: f64 = 12.0;
+    const HEIGHT: f64 = 400.0;
+    const TOP: f64 = 30.0;
+
+    let (lo, hi) = flow_bounds(layers);
+    let y = |v: u128| TOP + (v - lo) as f64 / (hi - lo) as f64 * HEIGHT;
+    let x = |i: usize| 20.0 + i as f64 * COLUMN;
+
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+        x(layers.len() - 1) + BAR + 80.0,
+        TOP + HEIGHT + 20.0
+    );
+    for (i, layer) in layers.iter().enumerate() {
+        out += &format!(
+            "  <text x=\"{}\" y=\"{}\" font-size=\"12\">{}</text>\n",
+            x(i),
+            TOP - 10.0,
+            layer.category
+        );
+        for link in &layer.links {
+            let (x0, x1) = (x(i - 1) + BAR, x(i));
+            let mid = (x0 + x1) / 2.0;
+            let (a0, a1, b0, b1) = (y(link.from.0), y(link.from.1), y(link.to.0), y(link.to.1));
+            out += &format!(
+                "  <path d=\"M{x0},{a0} C{mid},{a0} {mid},{b0} {x1},{b0} L{x1},{b1} C{mid},{b1} {mid},{a1} {x0},{a1} Z\" fill=\"steelblue\" fill-opacity=\"0.4\"/>\n"
+            );
+        }
+        for &(s, e) in &layer.segments {
+            out += &format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{BAR}\" height=\"{}\" fill=\"black\"/>\n",
+                x(i),
+                y(s),
+                (y(e) - y(s)).max(1.0)
+            );
+        }
+    }
+    out += "</svg>\n";
+    out
+}
+
+#[aoc(day5, part2, Flow)]
+fn part2_flow(input: &Input) -> String {
+    flow_ascii(&flow(input), 60)
+}
+
+// Printed after the runner's label; save everything from `<svg` on to view it.
+#[aoc(day5, part2, Svg)]
+fn part2_svg(input: &Input) -> String {
+    flow_svg(&flow(input))
 }
 
 #[cfg(test)]
@@ -153,11 +833,221 @@ mod tests {
     fn part1_example() {
         let example = include_str!("examples/day05.txt");
         assert_eq!(part1(&parse(example)), 35);
+        assert_eq!(part1_layered(&parse(example)), 35);
     }
 
     #[test]
     fn part2_example() {
         let example = include_str!("examples/day05.txt");
         assert_eq!(part2(&parse(example)), 46);
+        assert_eq!(part2_layered(&parse(example)), 46);
+        assert_eq!(part2_inverse(&parse(example)), 46);
+    }
+
+    #[test]
+    fn composed_map_matches_layers() {
+        let input = parse(include_str!("examples/day05.txt"));
+        let map = compose(&input);
+        for seed in 0..200 {
+            assert_eq!(map.get(seed), map_seed(&input, seed));
+        }
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        // Seeds 5 and 6 are in both ranges; the first one listed wins.
+        let example = "seeds: 7 1 3 1 12 1\n\nseed-to-location map:\n100 0 10\n200 5 2\n300 8 6";
+        let input = parse(example);
+        let map = compose(&input);
+        for seed in 0..20 {
+            assert_eq!(map.get(seed), map_seed(&input, seed));
+        }
+        assert_eq!(map_seed(&input, 7), 107);
+        assert_eq!(part1(&input), part1_layered(&input));
+        assert_eq!(part2(&input), part2_layered(&input));
+        assert_eq!(part2_inverse(&input), part2_layered(&input));
+
+        let example = "seeds: 0 20\n\nseed-to-location map:\n100 0 10\n200 5 2\n50 8 6";
+        let input = parse(example);
+        assert_eq!(part2(&input), part2_layered(&input));
+        assert_eq!(part2_inverse(&input), part2_layered(&input));
+
+        // Empty seed ranges contain no seed, wherever they would map to.
+        let example = "seeds: 13 6 43 0 4 0\n\nseed-to-soil map:\n56 0 10\n13 51 9\n30 31 2\n\nsoil-to-location map:\n5 57 4\n52 14 2";
+        let input = parse(example);
+        assert_eq!(part2(&input), 13);
+        assert_eq!(part2_layered(&input), 13);
+        assert_eq!(part2_inverse(&input), 13);
+    }
+
+    #[test]
+    fn seeds_for_locations_example() {
+        let input = parse(include_str!("examples/day05.txt"));
+        let range = (40, 60);
+        let seeds = seeds_for_locations(&input, range);
+        let expected: Vec<u64> = (0..200)
+            .filter(|&x| (range.0..range.1).contains(&(map_seed(&input, x) as u128)))
+            .collect();
+        let found: Vec<u64> = seeds
+            .iter()
+            .flat_map(|&(s, e)| s as u64..e.min(200) as u64)
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn category_graph() {
+        let example = include_str!("examples/day05.txt");
+        let input = parse(example);
+        assert_eq!(input.path("seed", "humidity").unwrap().len(), 6);
+        assert_eq!(input.convert("seed", "soil", 79), Ok(81));
+        assert_eq!(input.convert("soil", "fertilizer", 81), Ok(81));
+        assert_eq!(
+            input.convert("location", "seed", 0),
+            Err(AlmanacError::MissingLink(
+                "location".to_string(),
+                "seed".to_string()
+            ))
+        );
+
+        // Maps in any order give the same answers.
+        let (seeds, maps) = example.split_once("\n\n").unwrap();
+        let shuffled = format!("{seeds}\n\n{}", maps.trim_end().rsplit("\n\n").join("\n\n"));
+        assert_eq!(part1(&parse(&shuffled)), 35);
+
+        let cyclic = "seeds: 1\n\nseed-to-soil map:\n1 2 3\n\nsoil-to-seed map:\n1 2 3";
+        assert_eq!(
+            parse_almanac(cyclic).err(),
+            Some(AlmanacError::Cycle("seed".to_string()))
+        );
+    }
+
+    #[test]
+    fn range_validation() {
+        let example = include_str!("examples/day05.txt");
+        assert!(parse_strict(example).is_ok());
+
+        let bad = "seeds: 1\n\nseed-to-soil map:\n0 10 5\n20 12 5\n3 30 0\n\nsoil-to-location map:\n0 0 5\n2 10 5";
+        assert_eq!(
+            parse_almanac(bad).unwrap().range_issues(),
+            vec![
+                RangeIssue::ZeroLength(6),
+                RangeIssue::OverlappingSource(4, 5),
+                RangeIssue::DestinationCollision(9, 10),
+            ]
+        );
+        assert!(matches!(parse_strict(bad), Err(AlmanacError::Ranges(v)) if v.len() == 3));
+
+        // Every pair is reported, not only the range reaching furthest.
+        let nested = "seeds: 1\n\nseed-to-location map:\n100 0 10\n200 5 15\n300 8 1";
+        assert_eq!(
+            parse_almanac(nested).unwrap().range_issues(),
+            vec![
+                RangeIssue::OverlappingSource(4, 5),
+                RangeIssue::OverlappingSource(4, 6),
+                RangeIssue::OverlappingSource(5, 6),
+            ]
+        );
+        assert!(parse_strict_part1(nested).is_err());
+        assert_eq!(part1_strict(&parse_strict_part1(example).unwrap()), 35);
+        assert_eq!(part2_strict(&parse_strict_part2(example).unwrap()), 46);
+    }
+
+    #[test]
+    fn layer_stats_example() {
+        let stats = layer_stats(&parse(include_str!("examples/day05.txt")));
+        assert_eq!(stats.len(), 8);
+        assert_eq!(stats[7].category, "location");
+        assert!(stats.windows(2).all(|w| w[1].conserved(&w[0])));
+        assert!(stats.iter().all(|s| s.merged_length == 27));
+        let report = part2_stats(&parse(include_str!("examples/day05.txt")));
+        assert_eq!(
+            report.lines().next(),
+            Some("seed: 2 segments (2 merged), length 27 (27 merged)")
+        );
+        assert_eq!(report.lines().count(), 8);
+        assert!(!report.contains("not conserved"));
+        assert_eq!(
+            normalize(vec![(5, 7), (1, 3), (3, 4), (6, 9)]),
+            vec![(1, 4), (5, 9)]
+        );
+    }
+
+    #[test]
+    fn full_u64_domain() {
+        let top = u64::MAX;
+        // The first range ends exactly at 2^64 and the second starts there.
+        let example = format!(
+            "seeds: {} 10 5 3\n\nseed-to-soil map:\n0 {} 10\n{} 0 6\n\nsoil-to-location map:\n100 0 4",
+            top - 9,
+            top - 9,
+            top - 5
+        );
+        let input = parse(&example);
+        assert_eq!(map_seed(&input, top - 9), 100);
+        assert_eq!(map_seed(&input, top), 9);
+        assert_eq!(map_seed(&input, 5), top);
+        assert_eq!(map_seed(&input, 6), 6);
+        assert_eq!(part1(&input), 10);
+        assert_eq!(part1_layered(&input), 10);
+        assert_eq!(part2(&input), 4);
+        assert_eq!(part2_layered(&input), 4);
+        assert_eq!(part2_inverse(&input), 4);
+
+        let map = compose(&input);
+        for x in [0, 3, 4, 5, 6, 9, top - 9, top - 6, top - 5, top] {
+            assert_eq!(map.get(x), map_seed(&input, x));
+        }
+        // Seeds 6 and 7 land on top of the block mapped from the top of the domain.
+        let stats = layer_stats(&input);
+        assert_eq!((stats[1].length, stats[1].merged_length), (13, 11));
+        assert!(!stats[1].conserved(&stats[0]));
+
+        let overflow = format!("seeds: 1 1\n\nseed-to-soil map:\n0 {top} 2");
+        assert_eq!(
+            parse_almanac(&overflow).err(),
+            Some(AlmanacError::OutOfDomain(4))
+        );
+    }
+
+    #[test]
+    fn flow_diagram() {
+        let layers = flow(&parse(include_str!("examples/day05.txt")));
+        assert_eq!(layers.len(), 8);
+        for w in layers.windows(2) {
+            let from: Vec<Segment> = w[1].links.iter().map(|l| l.from).collect();
+            assert_eq!(normalize(from), w[0].segments);
+            assert_eq!(covered(&w[1].segments), 27);
+        }
+        assert_eq!(
+            layers[1].links,
+            vec![
+                Link {
+                    from: (55, 68),
+                    to: (57, 70)
+                },
+                Link {
+                    from: (79, 93),
+                    to: (81, 95)
+                },
+            ]
+        );
+
+        assert_eq!(
+            part2_flow(&parse(include_str!("examples/day05.txt")))
+                .lines()
+                .count(),
+            9
+        );
+        let ascii = flow_ascii(&layers, 20);
+        assert_eq!(ascii.lines().count(), 9);
+        assert!(ascii.starts_with("       seed |"));
+
+        let svg = part2_svg(&parse(include_str!("examples/day05.txt")));
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(
+            svg.matches("<path").count(),
+            layers.iter().map(|l| l.links.len()).sum()
+        );
     }
 }
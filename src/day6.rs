@@ -1,57 +1,301 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use num_bigint::BigUint;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+
 struct Game {
-    time: i64,
-    record: i64,
+    time: u128,
+    record: u128,
 }
 
-#[aoc_generator(day6)]
-fn parse(input: &str) -> Vec<Game> {
-    let mut lines = input.lines();
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    MissingLine(&'static str),
+    WrongLabel {
+        expected: &'static str,
+        found: String,
+    },
+    InvalidNumber(String),
+    ColumnMismatch {
+        times: usize,
+        distances: usize,
+    },
+}
 
-    let mut parse_line = || {
-        lines
-            .next()
-            .unwrap()
-            .split_ascii_whitespace()
-            .skip(1)
-            .map(|x| x.parse::<i64>().unwrap())
-    };
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingLine(label) => write!(f, "missing {label} line"),
+            ParseError::WrongLabel { expected, found } => {
+                write!(f, "expected {expected} line, found {found:?}")
+            }
+            ParseError::InvalidNumber(n) => write!(f, "invalid number {n:?}"),
+            ParseError::ColumnMismatch { times, distances } => {
+                write!(f, "{times} times but {distances} distances")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+// Digit columns of a `<label>: 1 2 3` line.
+fn parse_columns<'a>(
+    line: Option<&'a str>,
+    label: &'static str,
+) -> Result<Vec<&'a str>, ParseError> {
+    let line = line.ok_or(ParseError::MissingLine(label))?;
+    let columns = line
+        .strip_prefix(label)
+        .and_then(|l| l.strip_prefix(':'))
+        .ok_or_else(|| ParseError::WrongLabel {
+            expected: label,
+            found: line.to_string(),
+        })?
+        .split_ascii_whitespace()
+        .collect::<Vec<_>>();
+    match columns
+        .iter()
+        .find(|c| !c.bytes().all(|b| b.is_ascii_digit()))
+    {
+        Some(c) => Err(ParseError::InvalidNumber(c.to_string())),
+        None => Ok(columns),
+    }
+}
 
-    parse_line()
-        .zip(parse_line())
-        .map(|(time, record)| Game { time, record })
+fn parse_number(digits: &str) -> Result<u128, ParseError> {
+    digits
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber(digits.to_string()))
+}
+
+fn parse_table(input: &str) -> Result<(Vec<&str>, Vec<&str>), ParseError> {
+    let mut lines = input.lines();
+    let times = parse_columns(lines.next(), "Time")?;
+    let distances = parse_columns(lines.next(), "Distance")?;
+    if times.len() != distances.len() {
+        return Err(ParseError::ColumnMismatch {
+            times: times.len(),
+            distances: distances.len(),
+        });
+    }
+    Ok((times, distances))
+}
+
+#[aoc_generator(day6, part1)]
+fn parse(input: &str) -> Result<Vec<Game>, ParseError> {
+    let (times, distances) = parse_table(input)?;
+    times
+        .into_iter()
+        .zip(distances)
+        .map(|(time, record)| {
+            Ok(Game {
+                time: parse_number(time)?,
+                record: parse_number(record)?,
+            })
+        })
         .collect()
 }
 
-fn win_count(game: &Game) -> i64 {
+// The kerned race: every column of a line read as one number, straight from
+// the text so leading zeros inside the columns are kept.
+#[aoc_generator(day6, part2)]
+fn parse_kerned(input: &str) -> Result<Game, ParseError> {
+    let (times, distances) = parse_table(input)?;
+    Ok(Game {
+        time: parse_number(&times.concat())?,
+        record: parse_number(&distances.concat())?,
+    })
+}
+
+fn win_count_brute(game: &Game) -> u128 {
     (1..game.time)
         .filter(|charge| (game.time - charge) * charge > game.record)
-        .count() as i64
+        .count() as u128
+}
+
+// Charge times `c` with `c * (time - c) > record`. The distance is a parabola
+// symmetric around `time / 2`, so the winning charges are one range around it
+// and the first one is found from the integer square root of the
+// discriminant, then nudged to fix the rounding of the division. The
+// discriminant is computed in a BigUint, so every u128 time and record is
+// exact; `None` only ever means that no charge wins.
+pub fn winning_charges(time: u128, record: u128) -> Option<RangeInclusive<u128>> {
+    // A product that overflows u128 is certainly above the record.
+    let wins = |c: u128| c <= time && c.checked_mul(time - c).is_none_or(|d| d > record);
+    let square = BigUint::from(time) * time;
+    let four_record = BigUint::from(record) * 4u32;
+    if square < four_record {
+        return None;
+    }
+    let root = u128::try_from((square - four_record).sqrt()).unwrap();
+    let mut lo = (time - root) / 2;
+    while lo > 0 && wins(lo - 1) {
+        lo -= 1;
+    }
+    while lo <= time / 2 && !wins(lo) {
+        lo += 1;
+    }
+    if lo > time / 2 {
+        None
+    } else {
+        Some(lo..=time - lo)
+    }
+}
+
+// Winning charge times, if any.
+pub type Wins = Option<RangeInclusive<u128>>;
+
+pub trait BoatModel {
+    // Distance covered in a race of `time` ms after holding the button for
+    // `charge` ms of it.
+    fn distance(&self, time: u128, charge: u128) -> u128;
+
+    // Closed-form winning charges, for models that have one.
+    fn analytic(&self, _time: u128, _record: u128) -> Option<Wins> {
+        None
+    }
+}
+
+// The puzzle rule: every ms held adds 1 mm/ms of speed.
+pub struct Linear;
+
+// Every ms held adds the given speed.
+pub struct Accelerating(pub u128);
+
+// Like `Linear`, but the speed never exceeds the given maximum.
+pub struct SpeedCap(pub u128);
+
+// Like `Linear`, but the boat loses the given speed after every ms it moves.
+pub struct Friction(pub u128);
+
+impl BoatModel for Linear {
+    fn distance(&self, time: u128, charge: u128) -> u128 {
+        charge * (time - charge)
+    }
+
+    fn analytic(&self, time: u128, record: u128) -> Option<Wins> {
+        Some(winning_charges(time, record))
+    }
+}
+
+impl BoatModel for Accelerating {
+    fn distance(&self, time: u128, charge: u128) -> u128 {
+        self.0 * charge * (time - charge)
+    }
+
+    // `a * d > record` exactly when `d > record / a` rounded down.
+    fn analytic(&self, time: u128, record: u128) -> Option<Wins> {
+        if self.0 == 0 {
+            return Some(None);
+        }
+        Some(winning_charges(time, record / self.0))
+    }
+}
+
+impl BoatModel for SpeedCap {
+    fn distance(&self, time: u128, charge: u128) -> u128 {
+        charge.min(self.0) * (time - charge)
+    }
+
+    // Up to the cap this is `Linear`. Past it the distance only shrinks, and
+    // `cap * (time - c) > record` holds up to `time - record / cap - 1`.
+    fn analytic(&self, time: u128, record: u128) -> Option<Wins> {
+        let Some(linear) = winning_charges(time, record) else {
+            return Some(None);
+        };
+        let (lo, hi) = linear.into_inner();
+        if self.0 < lo {
+            return Some(None);
+        }
+        let mut end = hi.min(self.0);
+        if let Some(capped) = time.checked_sub(record / self.0 + 1) {
+            if capped > self.0 {
+                end = end.max(capped);
+            }
+        }
+        Some(Some(lo..=end))
+    }
+}
+
+impl BoatModel for Friction {
+    fn distance(&self, time: u128, charge: u128) -> u128 {
+        let steps = time - charge;
+        let moving = if self.0 == 0 {
+            steps
+        } else {
+            steps.min(charge.div_ceil(self.0))
+        };
+        moving * charge - self.0 * moving * moving.saturating_sub(1) / 2
+    }
+}
+
+pub fn count_brute(model: &dyn BoatModel, time: u128, record: u128) -> u128 {
+    (0..=time)
+        .filter(|&charge| model.distance(time, charge) > record)
+        .count() as u128
+}
+
+pub fn count_wins(model: &dyn BoatModel, time: u128, record: u128) -> u128 {
+    match model.analytic(time, record) {
+        Some(wins) => wins.map_or(0, |r| r.end() - r.start() + 1),
+        None => count_brute(model, time, record),
+    }
+}
+
+// Parses "linear", "accel:<speed>", "cap:<speed>" or "friction:<speed>".
+pub fn boat_model(spec: &str) -> Box<dyn BoatModel> {
+    let (name, arg) = match spec.split_once(':') {
+        Some((name, arg)) => (name, Some(arg.parse().unwrap())),
+        None => (spec, None),
+    };
+    match (name, arg) {
+        ("linear", None) => Box::new(Linear),
+        ("accel", Some(a)) => Box::new(Accelerating(a)),
+        ("cap", Some(cap)) if cap > 0 => Box::new(SpeedCap(cap)),
+        ("friction", Some(f)) => Box::new(Friction(f)),
+        _ => panic!("Wrong boat model: {spec}"),
+    }
+}
+
+fn win_count(game: &Game) -> u128 {
+    winning_charges(game.time, game.record).map_or(0, |r| r.end() - r.start() + 1)
+}
+
+// Counts themselves are exact, so only their product can overflow.
+fn product(mut counts: impl Iterator<Item = u128>) -> u128 {
+    counts
+        .try_fold(1u128, |acc, n| acc.checked_mul(n))
+        .expect("Product of win counts overflows u128")
 }
 
 #[aoc(day6, part1)]
-fn part1(input: &[Game]) -> i64 {
-    input.iter().map(win_count).product()
+fn part1(input: &[Game]) -> u128 {
+    product(input.iter().map(win_count))
+}
+
+// Boat model taken from AOC_DAY6_BOAT, e.g. `AOC_DAY6_BOAT=cap:10`.
+#[aoc(day6, part1, Model)]
+fn part1_model(input: &[Game]) -> u128 {
+    let model = boat_model(&env::var("AOC_DAY6_BOAT").unwrap_or("linear".to_string()));
+    product(
+        input
+            .iter()
+            .map(|g| count_wins(model.as_ref(), g.time, g.record)),
+    )
+}
+
+#[aoc(day6, part1, Brute)]
+fn part1_brute(input: &[Game]) -> u128 {
+    product(input.iter().map(win_count_brute))
 }
 
 #[aoc(day6, part2)]
-fn part2(input: &[Game]) -> i64 {
-    let time: i64 = input
-        .iter()
-        .map(|g| g.time.to_string())
-        .collect::<Vec<String>>()
-        .join("")
-        .parse()
-        .unwrap();
-    let record: i64 = input
-        .iter()
-        .map(|g| g.record.to_string())
-        .collect::<Vec<String>>()
-        .join("")
-        .parse()
-        .unwrap();
-    win_count(&Game { time, record })
+fn part2(input: &Game) -> u128 {
+    win_count(input)
 }
 
 #[cfg(test)]
@@ -61,12 +305,102 @@ mod tests {
     #[test]
     fn part1_example() {
         let example = include_str!("examples/day06.txt");
-        assert_eq!(part1(&parse(example)), 288);
+        assert_eq!(part1(&parse(example).unwrap()), 288);
+        assert_eq!(part1_brute(&parse(example).unwrap()), 288);
     }
 
     #[test]
     fn part2_example() {
         let example = include_str!("examples/day06.txt");
-        assert_eq!(part2(&parse(example)), 71503);
+        assert_eq!(part2(&parse_kerned(example).unwrap()), 71503);
+
+        // Joined columns can be far beyond i64, and so can the count.
+        let example = "Time: 10000000000 0000000000\nDistance: 1 0";
+        assert_eq!(
+            part2(&parse_kerned(example).unwrap()),
+            99_999_999_999_999_999_999
+        );
+    }
+
+    #[test]
+    fn winning_charges_exact() {
+        assert_eq!(winning_charges(7, 9), Some(2..=5));
+        assert_eq!(winning_charges(30, 200), Some(11..=19));
+        assert_eq!(winning_charges(4, 4), None);
+        assert_eq!(winning_charges(0, 0), None);
+        assert_eq!(winning_charges(1 << 70, 5), Some(1..=(1 << 70) - 1));
+        assert_eq!(
+            winning_charges(u128::MAX, u128::MAX),
+            Some(2..=u128::MAX - 2)
+        );
+        assert_eq!(winning_charges(1 << 64, 1 << 126), None);
+        for time in 0..40 {
+            for record in 0..=time * time / 4 + 1 {
+                let game = Game { time, record };
+                let count = winning_charges(time, record).map_or(0, |r| r.end() - r.start() + 1);
+                assert_eq!(count, win_count_brute(&game));
+            }
+        }
+
+        // Around 1e19 a float square root can no longer resolve single units.
+        let time: u128 = 10_000_000_000_000_000_000;
+        let record = (time / 2 - 1) * (time / 2 + 1);
+        assert_eq!(winning_charges(time, record), Some(time / 2..=time / 2));
+    }
+
+    #[test]
+    fn boat_models() {
+        for spec in [
+            "linear", "accel:1", "accel:3", "accel:0", "cap:1", "cap:4", "cap:9", "cap:100",
+        ] {
+            let model = boat_model(spec);
+            for time in 0..30 {
+                for record in 0..=time * time / 2 {
+                    assert_eq!(
+                        count_wins(model.as_ref(), time, record),
+                        count_brute(model.as_ref(), time, record),
+                        "{spec} {time} {record}"
+                    );
+                }
+            }
+        }
+
+        assert_eq!(Friction(0).distance(7, 3), 12);
+        // Speeds 3, 1, then stopped.
+        assert_eq!(Friction(2).distance(7, 3), 4);
+        assert_eq!(count_wins(&Friction(2), 7, 3), 4);
+    }
+
+    #[test]
+    fn kerned_parsing() {
+        let padded = "Time:      7  05   30\nDistance:  9  040  200";
+        let race = parse_kerned(padded).unwrap();
+        assert_eq!((race.time, race.record), (70530, 9040200));
+
+        let huge = parse_kerned("Time: 1000000000 0000000000\nDistance: 1 0").unwrap();
+        assert_eq!(huge.time, 10_000_000_000_000_000_000);
+
+        assert_eq!(
+            parse("Time: 7 15\nDistance: 9").err(),
+            Some(ParseError::ColumnMismatch {
+                times: 2,
+                distances: 1
+            })
+        );
+        assert_eq!(
+            parse_kerned("Time: 7\nRecord: 9").err(),
+            Some(ParseError::WrongLabel {
+                expected: "Distance",
+                found: "Record: 9".to_string()
+            })
+        );
+        assert_eq!(
+            parse("Time: 7").err(),
+            Some(ParseError::MissingLine("Distance"))
+        );
+        assert_eq!(
+            parse("Time: 7 -1\nDistance: 9 1").err(),
+            Some(ParseError::InvalidNumber("-1".to_string()))
+        );
     }
 }
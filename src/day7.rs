@@ -1,90 +1,773 @@
-use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
 
 use aoc_runner_derive::aoc;
+use itertools::Itertools;
 
-fn map_char_p1(c: char) -> char {
-    match c {
-        'T' => 'A',
-        'J' => 'B',
-        'Q' => 'C',
-        'K' => 'D',
-        'A' => 'E',
-        '2'..='9' => c,
-        _ => panic!("Wrong char {c}"),
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Card {
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Jack,
+    Queen,
+    King,
+    Ace,
+}
+
+impl Card {
+    pub const ALL: [Card; 13] = [
+        Card::Two,
+        Card::Three,
+        Card::Four,
+        Card::Five,
+        Card::Six,
+        Card::Seven,
+        Card::Eight,
+        Card::Nine,
+        Card::Ten,
+        Card::Jack,
+        Card::Queen,
+        Card::King,
+        Card::Ace,
+    ];
+
+    pub fn from_char(c: char) -> Card {
+        match c {
+            '2'..='9' => Card::ALL[c as usize - '2' as usize],
+            'T' => Card::Ten,
+            'J' => Card::Jack,
+            'Q' => Card::Queen,
+            'K' => Card::King,
+            'A' => Card::Ace,
+            _ => panic!("Wrong char {c}"),
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        "23456789TJQKA".as_bytes()[self as usize] as char
     }
 }
 
-fn map_char_p2(c: char) -> char {
-    match c {
-        'T' => 'A',
-        'J' => '0',
-        'Q' => 'C',
-        'K' => 'D',
-        'A' => 'E',
-        '2'..='9' => c,
-        _ => panic!("Wrong char {c}"),
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HandType {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    FullHouse,
+    FourOfAKind,
+    FiveOfAKind,
+}
+
+impl HandType {
+    // Type of a hand whose card counts, largest first, are `counts`. Hands
+    // larger than five cards get the best type their counts reach.
+    fn from_counts(counts: &[usize]) -> HandType {
+        match counts {
+            [a, ..] if *a >= 5 => HandType::FiveOfAKind,
+            [4, ..] => HandType::FourOfAKind,
+            [3, b, ..] if *b >= 2 => HandType::FullHouse,
+            [3, ..] => HandType::ThreeOfAKind,
+            [2, 2, ..] => HandType::TwoPair,
+            [2, ..] => HandType::OnePair,
+            _ => HandType::HighCard,
+        }
     }
 }
 
-struct Hand {
-    cards: String,
-    bid: i64,
+impl fmt::Display for HandType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            HandType::HighCard => "High card",
+            HandType::OnePair => "One pair",
+            HandType::TwoPair => "Two pair",
+            HandType::ThreeOfAKind => "Three of a kind",
+            HandType::FullHouse => "Full house",
+            HandType::FourOfAKind => "Four of a kind",
+            HandType::FiveOfAKind => "Five of a kind",
+        };
+        write!(f, "{name}")
+    }
 }
 
-fn parse(input: &str, map_char: fn(char) -> char) -> Vec<Hand> {
-    input
-        .lines()
-        .map(|l| {
-            let parts: Vec<&str> = l.split(' ').collect();
-            Hand {
-                cards: parts[0].chars().map(map_char).collect(),
-                bid: parts[1].parse().unwrap(),
-            }
-        })
-        .collect()
+fn counts(cards: &[Card], wildcards: &[Card]) -> Vec<usize> {
+    let mut count = [0; 13];
+    let mut wild = 0;
+    for &c in cards {
+        if wildcards.contains(&c) {
+            wild += 1;
+        } else {
+            count[c as usize] += 1;
+        }
+    }
+    let mut counts: Vec<usize> = count.into_iter().filter(|&n| n > 0).collect();
+    counts.sort_by(|a, b| b.cmp(a));
+    match counts.first_mut() {
+        Some(first) => *first += wild,
+        None => counts.push(wild),
+    }
+    counts
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Suit {
+    Clubs,
+    Diamonds,
+    Hearts,
+    Spades,
 }
 
-fn score_hand_p1(hand: &Hand) -> i64 {
-    let mut count: HashMap<char, u32> = HashMap::new();
-    for c in hand.cards.chars() {
-        *count.entry(c).or_default() += 1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SuitedCard {
+    pub card: Card,
+    pub suit: Suit,
+}
+
+impl SuitedCard {
+    // Parses cards such as "AS", "Td" or "2h".
+    pub fn parse(s: &str) -> Option<SuitedCard> {
+        let mut chars = s.chars();
+        let card = chars.next()?;
+        let card = *Card::ALL.iter().find(|c| c.to_char() == card)?;
+        let suit = match chars.next()?.to_ascii_uppercase() {
+            'C' => Suit::Clubs,
+            'D' => Suit::Diamonds,
+            'H' => Suit::Hearts,
+            'S' => Suit::Spades,
+            _ => return None,
+        };
+        chars.next().is_none().then_some(SuitedCard { card, suit })
     }
-    count.values().map(|v| 4_i64.pow(*v)).sum()
 }
 
-fn score_hand_p2(hand: &Hand) -> i64 {
-    let mut count: HashMap<char, u32> = HashMap::new();
-    for c in hand.cards.chars() {
-        *count.entry(c).or_default() += 1;
+// Hand categories of both Camel Cards and poker. Camel Cards only reaches
+// the ones that `HandType` has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Category {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    Straight,
+    Flush,
+    FullHouse,
+    FourOfAKind,
+    StraightFlush,
+    FiveOfAKind,
+}
+
+impl Category {
+    pub const ALL: [Category; 10] = [
+        Category::HighCard,
+        Category::OnePair,
+        Category::TwoPair,
+        Category::ThreeOfAKind,
+        Category::Straight,
+        Category::Flush,
+        Category::FullHouse,
+        Category::FourOfAKind,
+        Category::StraightFlush,
+        Category::FiveOfAKind,
+    ];
+}
+
+impl From<HandType> for Category {
+    fn from(hand_type: HandType) -> Category {
+        match hand_type {
+            HandType::HighCard => Category::HighCard,
+            HandType::OnePair => Category::OnePair,
+            HandType::TwoPair => Category::TwoPair,
+            HandType::ThreeOfAKind => Category::ThreeOfAKind,
+            HandType::FullHouse => Category::FullHouse,
+            HandType::FourOfAKind => Category::FourOfAKind,
+            HandType::FiveOfAKind => Category::FiveOfAKind,
+        }
     }
-    if let Some(add) = count.remove(&'0') {
-        if let Some((k, _)) = count.iter().max_by_key(|(_, v)| *v) {
-            count.entry(*k).and_modify(|v| *v += add);
-        } else {
-            count.insert('0', add);
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Category::Straight => write!(f, "Straight"),
+            Category::Flush => write!(f, "Flush"),
+            Category::StraightFlush => write!(f, "Straight flush"),
+            Category::HighCard => HandType::HighCard.fmt(f),
+            Category::OnePair => HandType::OnePair.fmt(f),
+            Category::TwoPair => HandType::TwoPair.fmt(f),
+            Category::ThreeOfAKind => HandType::ThreeOfAKind.fmt(f),
+            Category::FullHouse => HandType::FullHouse.fmt(f),
+            Category::FourOfAKind => HandType::FourOfAKind.fmt(f),
+            Category::FiveOfAKind => HandType::FiveOfAKind.fmt(f),
         }
     }
-    count.values().map(|v| 4_i64.pow(*v)).sum()
 }
 
-fn solve(input: &str, map_char: fn(char) -> char, score_hand: fn(hand: &Hand) -> i64) -> i64 {
-    let mut input = parse(input, map_char);
-    input.sort_by(|a, b| (score_hand(a), &a.cards).cmp(&(score_hand(b), &b.cards)));
+// How a hand ranks under some rules: by category, then card counts, then
+// the tie-break. Only meaningful between hands evaluated with the same rules.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Evaluation {
+    pub category: Category,
+    pattern: Vec<usize>,
+    key: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreak {
+    // Compare the first cards, then the second ones and so on.
+    Positional,
+    // Compare the strongest cards of each hand, then the next strongest.
+    Sorted,
+    // Like `Sorted`, but cards of larger groups come first, as poker
+    // compares the pair before the kickers.
+    Grouped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    // Strength of every card, indexed by `Card as usize`.
+    strength: [usize; 13],
+    // Cards that count as whatever makes the best type.
+    wildcards: Vec<Card>,
+    pub hand_size: usize,
+    pub tie_break: TieBreak,
+    // Whether `hand_size` cards of consecutive ranks, or of a single suit,
+    // make a category of their own as in poker. Straights follow the
+    // natural card order, with an ace also allowed below the two.
+    pub straights: bool,
+    pub flushes: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RulesError {
+    UnknownKey(String),
+    BadValue(String, String),
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RulesError::UnknownKey(key) => write!(f, "unknown key {key}"),
+            RulesError::BadValue(key, value) => write!(f, "bad value {value:?} for {key}"),
+        }
+    }
+}
+
+impl Rules {
+    pub fn standard() -> Rules {
+        Rules::with_order("AKQJT98765432").unwrap()
+    }
+
+    // Jacks are jokers: they count as whatever card makes the best type,
+    // but are the weakest card when breaking ties.
+    pub fn jokers() -> Rules {
+        let mut rules = Rules::with_order("AKQT98765432J").unwrap();
+        rules.wildcards = vec![Card::Jack];
+        rules
+    }
+
+    // `order` lists all 13 cards, strongest first.
+    pub fn with_order(order: &str) -> Option<Rules> {
+        let mut strength = [usize::MAX; 13];
+        for (i, c) in order.chars().rev().enumerate() {
+            let card = Card::ALL.iter().find(|card| card.to_char() == c)?;
+            strength[*card as usize] = i;
+        }
+        if order.len() != 13 || strength.contains(&usize::MAX) {
+            return None;
+        }
+        Some(Rules {
+            strength,
+            wildcards: Vec::new(),
+            hand_size: 5,
+            tie_break: TieBreak::Positional,
+            straights: false,
+            flushes: false,
+        })
+    }
+
+    // The same rules with every card standing for itself.
+    pub fn natural(&self) -> Rules {
+        Rules {
+            wildcards: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    pub fn poker() -> Rules {
+        let mut rules = Rules::standard();
+        rules.tie_break = TieBreak::Grouped;
+        rules.straights = true;
+        rules.flushes = true;
+        rules
+    }
+
+    // Reads `key = value` lines, `#` starting a comment. Keys are `order`,
+    // `wildcards` (cards, may be empty), `hand_size`, `tie_break`
+    // (`positional`, `sorted` or `grouped`), and `straights` and `flushes`
+    // (`true` or `false`); anything left out keeps the standard rules.
+    pub fn parse(text: &str) -> Result<Rules, RulesError> {
+        let mut rules = Rules::standard();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| RulesError::UnknownKey(line.to_string()))?;
+            let bad = || RulesError::BadValue(key.to_string(), value.to_string());
+            match key {
+                "order" => {
+                    let ordered = Rules::with_order(value).ok_or_else(bad)?;
+                    rules.strength = ordered.strength;
+                }
+                "wildcards" => {
+                    rules.wildcards = value
+                        .chars()
+                        .map(|c| Card::ALL.into_iter().find(|card| card.to_char() == c))
+                        .collect::<Option<_>>()
+                        .ok_or_else(bad)?;
+                }
+                "hand_size" => {
+                    rules.hand_size = value.parse().ok().filter(|&n| n > 0).ok_or_else(bad)?;
+                }
+                "tie_break" => {
+                    rules.tie_break = match value {
+                        "positional" => TieBreak::Positional,
+                        "sorted" => TieBreak::Sorted,
+                        "grouped" => TieBreak::Grouped,
+                        _ => return Err(bad()),
+                    }
+                }
+                "straights" => rules.straights = value.parse().map_err(|_| bad())?,
+                "flushes" => rules.flushes = value.parse().map_err(|_| bad())?,
+                _ => return Err(RulesError::UnknownKey(key.to_string())),
+            }
+        }
+        Ok(rules)
+    }
+
+    fn strength(&self, card: Card) -> usize {
+        self.strength[card as usize]
+    }
+
+    // Card counts, largest first, with all wildcards joining the most
+    // common other card. Hands are ordered by these before any tie-break.
+    pub fn pattern(&self, cards: &[Card]) -> Vec<usize> {
+        counts(cards, &self.wildcards)
+    }
+
+    pub fn hand_type(&self, cards: &[Card]) -> HandType {
+        HandType::from_counts(&self.pattern(cards))
+    }
+
+    fn tie_break_key(&self, cards: &[Card]) -> Vec<usize> {
+        let mut key: Vec<usize> = cards.iter().map(|&c| self.strength(c)).collect();
+        match self.tie_break {
+            TieBreak::Positional => {}
+            TieBreak::Sorted => key.sort_by(|a, b| b.cmp(a)),
+            TieBreak::Grouped => {
+                let group = |s: &usize| key.iter().filter(|&k| k == s).count();
+                let mut grouped = key.clone();
+                grouped.sort_by_key(|s| std::cmp::Reverse((group(s), *s)));
+                key = grouped;
+            }
+        }
+        key
+    }
+
+    // Natural rank of the top card of the best straight the cards make, with
+    // wildcards filling any gaps. Straights rank by the natural order even
+    // when `order` changes card strengths, so a jack-high straight still
+    // beats a ten-high one under joker rules.
+    fn straight(&self, cards: &[Card]) -> Option<usize> {
+        let n = cards.len();
+        let fixed: Vec<usize> = cards
+            .iter()
+            .filter(|c| !self.wildcards.contains(c))
+            .map(|&c| c as usize)
+            .collect();
+        let mut ranks = fixed.clone();
+        ranks.sort();
+        ranks.dedup();
+        if !self.straights || !(2..=13).contains(&n) || ranks.len() != fixed.len() {
+            return None;
+        }
+        let ace = Card::Ace as usize;
+        let fits = |low: usize, top: usize| ranks.iter().all(|&r| (low..=top).contains(&r));
+        // Highest straights first, down to the one with the ace below the two.
+        (n - 1..=ace)
+            .rev()
+            .find(|&top| fits(top + 1 - n, top))
+            .or_else(|| {
+                ranks
+                    .iter()
+                    .all(|&r| r == ace || r + 1 < n)
+                    .then_some(n - 2)
+            })
+    }
+
+    fn evaluation(&self, cards: &[Card], flush: bool) -> Evaluation {
+        let pattern = self.pattern(cards);
+        let mut category = Category::from(HandType::from_counts(&pattern));
+        let straight = self.straight(cards);
+        let made = match (straight, flush) {
+            (Some(_), true) => Category::StraightFlush,
+            (Some(_), false) => Category::Straight,
+            (None, true) => Category::Flush,
+            (None, false) => Category::HighCard,
+        };
+        category = category.max(made);
+        // Straights only compare by their top card.
+        let (pattern, key) = match (category, straight) {
+            (Category::Straight | Category::StraightFlush, Some(top)) => (Vec::new(), vec![top]),
+            _ => (pattern, self.tie_break_key(cards)),
+        };
+        Evaluation {
+            category,
+            pattern,
+            key,
+        }
+    }
+
+    pub fn evaluate(&self, cards: &[Card]) -> Evaluation {
+        self.evaluation(cards, false)
+    }
+
+    pub fn evaluate_suited(&self, cards: &[SuitedCard]) -> Evaluation {
+        let ranks: Vec<Card> = cards.iter().map(|c| c.card).collect();
+        let flush = self.flushes && cards.iter().all(|c| c.suit == cards[0].suit);
+        self.evaluation(&ranks, flush)
+    }
+
+    // Best `hand_size` cards out of however many were dealt, e.g. the best
+    // five of seven in Texas hold'em.
+    pub fn best_hand(&self, cards: &[SuitedCard]) -> Option<(Vec<SuitedCard>, Evaluation)> {
+        cards
+            .iter()
+            .copied()
+            .combinations(self.hand_size)
+            .map(|hand| {
+                let evaluation = self.evaluate_suited(&hand);
+                (hand, evaluation)
+            })
+            .max_by(|a, b| a.1.cmp(&b.1))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hand {
+    pub cards: Vec<Card>,
+    pub bid: i64,
+    // Type of the cards as dealt, and after wildcards are played.
+    pub natural_type: Category,
+    pub hand_type: Category,
+    // What hands are ranked by. Only meaningful between hands built with
+    // the same rules.
+    pub evaluation: Evaluation,
+}
+
+impl Hand {
+    pub fn new(cards: Vec<Card>, bid: i64, rules: &Rules) -> Hand {
+        assert_eq!(cards.len(), rules.hand_size, "Wrong hand size");
+        let evaluation = rules.evaluate(&cards);
+        Hand {
+            natural_type: rules.natural().evaluate(&cards).category,
+            hand_type: evaluation.category,
+            evaluation,
+            cards,
+            bid,
+        }
+    }
+}
+
+fn parse(input: &str, rules: &Rules) -> Vec<Hand> {
+    input
+        .lines()
+        .map(|l| {
+            let parts: Vec<&str> = l.split(' ').collect();
+            Hand::new(
+                parts[0].chars().map(Card::from_char).collect(),
+                parts[1].parse().unwrap(),
+                rules,
+            )
+        })
+        .collect()
+}
+
+// Hands from weakest to strongest, so a hand's rank is its index plus one.
+pub fn rank(input: &str, rules: &Rules) -> Vec<Hand> {
+    let mut input = parse(input, rules);
+    input.sort_by(|a, b| a.evaluation.cmp(&b.evaluation));
     input
+}
+
+fn solve(input: &str, rules: &Rules) -> i64 {
+    rank(input, rules)
         .iter()
         .enumerate()
         .map(|(index, hand)| ((index as i64) + 1) * hand.bid)
         .sum()
 }
 
+// One row per ranked hand: rank, cards, bid, natural type, type after
+// wildcards and winnings.
+fn ranking_rows(ranked: &[Hand]) -> Vec<[String; 6]> {
+    ranked
+        .iter()
+        .enumerate()
+        .map(|(index, hand)| {
+            let rank = index as i64 + 1;
+            [
+                rank.to_string(),
+                hand.cards.iter().map(|c| c.to_char()).collect(),
+                hand.bid.to_string(),
+                hand.natural_type.to_string(),
+                hand.hand_type.to_string(),
+                (rank * hand.bid).to_string(),
+            ]
+        })
+        .collect()
+}
+
+const RANKING_HEADER: [&str; 6] = ["rank", "cards", "bid", "type", "effective type", "winnings"];
+
+pub fn ranking_csv(ranked: &[Hand]) -> String {
+    let mut out = RANKING_HEADER.join(",") + "\n";
+    for row in ranking_rows(ranked) {
+        out += &(row.join(",") + "\n");
+    }
+    out
+}
+
+pub fn ranking_table(ranked: &[Hand]) -> String {
+    let rows = ranking_rows(ranked);
+    let mut widths = RANKING_HEADER.map(|h| h.len());
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.len());
+        }
+    }
+    let line = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, w)| format!("{cell:<w$}"))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+            + "\n"
+    };
+    let mut out = line(RANKING_HEADER.to_vec());
+    out += &(widths.map(|w| "-".repeat(w)).join("-+-") + "\n");
+    for row in &rows {
+        out += &line(row.iter().map(|c| c.as_str()).collect());
+    }
+    let total: i64 = ranked
+        .iter()
+        .enumerate()
+        .map(|(i, h)| (i as i64 + 1) * h.bid)
+        .sum();
+    out += &format!("total winnings: {total}\n");
+    out
+}
+
 #[aoc(day7, part1)]
 fn part1(input: &str) -> i64 {
-    solve(input, map_char_p1, score_hand_p1)
+    solve(input, &Rules::standard())
+}
+
+// Rules loaded from the file named by AOC_DAY7_RULES, see `Rules::parse`, or the
+// standard rules if it is unset.
+#[aoc(day7, part1, Rules)]
+fn part1_rules(input: &str) -> Result<i64, String> {
+    let rules = match env::var("AOC_DAY7_RULES") {
+        Ok(path) => {
+            let text = fs::read_to_string(&path)
+                .map_err(|e| format!("Cannot read rules from {path}: {e}"))?;
+            Rules::parse(&text).map_err(|e| format!("Wrong rules in {path}: {e}"))?
+        }
+        Err(_) => Rules::standard(),
+    };
+    Ok(solve(input, &rules))
 }
 
 #[aoc(day7, part2)]
 fn part2(input: &str) -> i64 {
-    solve(input, map_char_p2, score_hand_p2)
+    solve(input, &Rules::jokers())
+}
+
+#[aoc(day7, part1, Table)]
+fn part1_table(input: &str) -> String {
+    ranking_table(&rank(input, &Rules::standard()))
+}
+
+#[aoc(day7, part2, Table)]
+fn part2_table(input: &str) -> String {
+    ranking_table(&rank(input, &Rules::jokers()))
+}
+
+// Best pattern reachable by replacing every wildcard with every card in
+// turn, as opposed to `Rules::pattern`, which moves them all onto the most
+// common card.
+pub fn best_substitution(rules: &Rules, cards: &[Card]) -> Vec<usize> {
+    let wild: Vec<usize> = (0..cards.len())
+        .filter(|&i| rules.wildcards.contains(&cards[i]))
+        .collect();
+    if wild.is_empty() {
+        return counts(cards, &[]);
+    }
+    let mut hand = cards.to_vec();
+    std::iter::repeat_n(Card::ALL, wild.len())
+        .multi_cartesian_product()
+        .map(|subst| {
+            for (&i, c) in wild.iter().zip(subst) {
+                hand[i] = c;
+            }
+            counts(&hand, &[])
+        })
+        .max()
+        .unwrap()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Disagreement {
+    pub cards: Vec<Card>,
+    pub heuristic: HandType,
+    pub best: HandType,
+}
+
+pub fn verify_wildcards(
+    rules: &Rules,
+    hands: impl IntoIterator<Item = Vec<Card>>,
+) -> Vec<Disagreement> {
+    hands
+        .into_iter()
+        .filter_map(|cards| {
+            let heuristic = rules.pattern(&cards);
+            let best = best_substitution(rules, &cards);
+            (heuristic != best).then(|| Disagreement {
+                heuristic: HandType::from_counts(&heuristic),
+                best: HandType::from_counts(&best),
+                cards,
+            })
+        })
+        .collect()
+}
+
+// Every hand of `size` cards up to the order of the cards, which is all the
+// type of a hand depends on.
+pub fn all_hands(size: usize) -> impl Iterator<Item = Vec<Card>> {
+    Card::ALL.into_iter().combinations_with_replacement(size)
+}
+
+#[aoc(day7, part2, Verify)]
+fn part2_verify(input: &str) -> String {
+    let rules = Rules::jokers();
+    let hands = parse(input, &rules);
+    let disagreements = verify_wildcards(&rules, hands.iter().map(|h| h.cards.clone()));
+    if disagreements.is_empty() {
+        return format!("joker heuristic is optimal for all {} hands", hands.len());
+    }
+    disagreements
+        .iter()
+        .map(|d| {
+            let cards: String = d.cards.iter().map(|c| c.to_char()).collect();
+            format!("{cards}: heuristic {}, best {}", d.heuristic, d.best)
+        })
+        .join("\n")
+}
+
+// Number of the `13^hand_size` equally likely card sequences that have each
+// hand type, enumerating each multiset of cards once and weighting it by the
+// number of orders its cards can come in. Both 13^34 and 34! still fit in a
+// u128, and larger hands would take far too long to enumerate anyway.
+pub fn type_frequencies(rules: &Rules) -> [u128; 10] {
+    assert!(
+        rules.hand_size <= 34,
+        "Wrong hand size for statistics: {}",
+        rules.hand_size
+    );
+    let factorial = |n: usize| (1..=n as u128).product::<u128>();
+    let mut frequencies = [0; 10];
+    for cards in all_hands(rules.hand_size) {
+        let orders = cards
+            .iter()
+            .dedup_with_count()
+            .fold(factorial(cards.len()), |n, (k, _)| n / factorial(k));
+        frequencies[rules.evaluate(&cards).category as usize] += orders;
+    }
+    frequencies
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeStats {
+    pub hand_type: Category,
+    pub probability: f64,
+    pub expected: f64,
+    pub observed: usize,
+}
+
+// Compares how often each hand type shows up among `hands` with how often
+// it would if every card were drawn uniformly at random.
+pub fn type_stats(rules: &Rules, hands: &[Hand]) -> Vec<TypeStats> {
+    let frequencies = type_frequencies(rules);
+    let total: u128 = frequencies.iter().sum();
+    Category::ALL
+        .iter()
+        .zip(frequencies)
+        .map(|(&hand_type, frequency)| {
+            let probability = frequency as f64 / total as f64;
+            TypeStats {
+                hand_type,
+                probability,
+                expected: probability * hands.len() as f64,
+                observed: hands.iter().filter(|h| h.hand_type == hand_type).count(),
+            }
+        })
+        .collect()
+}
+
+fn type_stats_table(rules: &Rules, input: &str) -> String {
+    let hands = parse(input, rules);
+    let mut out = format!(
+        "{:<15} | {:>11} | {:>8} | {:>8}\n",
+        "type", "probability", "expected", "observed"
+    );
+    // Cards carry no suits here, so flushes never show up.
+    for s in type_stats(rules, &hands) {
+        if s.probability == 0.0 && s.observed == 0 {
+            continue;
+        }
+        out += &format!(
+            "{:<15} | {:>11.6} | {:>8.1} | {:>8}\n",
+            s.hand_type.to_string(),
+            s.probability,
+            s.expected,
+            s.observed
+        );
+    }
+    out
+}
+
+#[aoc(day7, part1, Stats)]
+fn part1_stats(input: &str) -> String {
+    type_stats_table(&Rules::standard(), input)
+}
+
+#[aoc(day7, part2, Stats)]
+fn part2_stats(input: &str) -> String {
+    type_stats_table(&Rules::jokers(), input)
 }
 
 #[cfg(test)]
@@ -102,4 +785,181 @@ mod tests {
         let example = include_str!("examples/day07.txt");
         assert_eq!(part2(example), 5905);
     }
+
+    #[test]
+    fn hand_types() {
+        let hand_type = |cards: &str, rules: &Rules| {
+            let cards: Vec<Card> = cards.chars().map(Card::from_char).collect();
+            rules.hand_type(&cards)
+        };
+        assert_eq!(hand_type("KK677", &Rules::standard()), HandType::TwoPair);
+        assert_eq!(hand_type("KTJJT", &Rules::standard()), HandType::TwoPair);
+        assert_eq!(hand_type("KTJJT", &Rules::jokers()), HandType::FourOfAKind);
+        assert_eq!(hand_type("JJJJJ", &Rules::jokers()), HandType::FiveOfAKind);
+        assert_eq!(
+            hand_type("23332", &Rules::standard()).to_string(),
+            "Full house"
+        );
+        assert_eq!(Card::from_char('T').to_char(), 'T');
+    }
+
+    #[test]
+    fn custom_rules() {
+        let example = include_str!("examples/day07.txt");
+        let jokers = Rules::parse("order = AKQT98765432J\nwildcards = J # jokers").unwrap();
+        assert_eq!(jokers, Rules::jokers());
+        assert_eq!(solve(example, &jokers), 5905);
+
+        // Ties in the example are decided by the first cards either way.
+        let sorted = Rules::parse("tie_break = sorted").unwrap();
+        assert_eq!(solve(example, &sorted), 6440);
+        let cards = |s: &str| s.chars().map(Card::from_char).collect::<Vec<_>>();
+        assert!(sorted.evaluate(&cards("23456")) < sorted.evaluate(&cards("65433")));
+        assert_eq!(
+            sorted.evaluate(&cards("26543")),
+            sorted.evaluate(&cards("65432"))
+        );
+
+        let wild = Rules::parse("wildcards = 23\nhand_size = 3").unwrap();
+        assert_eq!(wild.hand_type(&cards("2A3")), HandType::ThreeOfAKind);
+        // AKQ < AK2 (a pair with the wildcard) < QQ2.
+        assert_eq!(solve("AK2 1\nAKQ 2\nQQ2 3", &wild), 13);
+
+        let big = Rules::parse("hand_size = 7").unwrap();
+        assert!(big.evaluate(&cards("AAAKKK2")) > big.evaluate(&cards("AAAKK22")));
+        assert_eq!(big.hand_type(&cards("AAAAAAK")), HandType::FiveOfAKind);
+
+        assert_eq!(
+            Rules::parse("order = AKQ"),
+            Err(RulesError::BadValue("order".to_string(), "AKQ".to_string()))
+        );
+        assert_eq!(
+            Rules::parse("suits = 4"),
+            Err(RulesError::UnknownKey("suits".to_string()))
+        );
+    }
+
+    #[test]
+    fn ranking_export() {
+        let example = include_str!("examples/day07.txt");
+        let ranked = rank(example, &Rules::jokers());
+        let csv = ranking_csv(&ranked);
+        assert_eq!(
+            csv.lines().next(),
+            Some("rank,cards,bid,type,effective type,winnings")
+        );
+        assert_eq!(
+            csv.lines().nth(5),
+            Some("5,KTJJT,220,Two pair,Four of a kind,1100")
+        );
+
+        let table = part2_table(example);
+        assert_eq!(table.lines().count(), 8);
+        assert!(table
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("1    | 32T3K | 765 | One pair "));
+        assert!(table.ends_with("total winnings: 5905\n"));
+
+        // Types follow the rules the hands were ranked with.
+        let rules = Rules::parse("straights = true\nwildcards = J").unwrap();
+        let csv = ranking_csv(&rank("23456 1\nJ3457 2\nAAKK2 3", &rules));
+        assert_eq!(
+            csv.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "1,AAKK2,3,Two pair,Two pair,3",
+                "2,23456,1,Straight,Straight,2",
+                "3,J3457,2,High card,Straight,6"
+            ]
+        );
+    }
+
+    #[test]
+    fn joker_heuristic_is_optimal() {
+        let example = include_str!("examples/day07.txt");
+        assert_eq!(
+            part2_verify(example),
+            "joker heuristic is optimal for all 5 hands"
+        );
+        assert_eq!(all_hands(5).count(), 6188);
+        assert!(verify_wildcards(&Rules::jokers(), all_hands(5)).is_empty());
+
+        let wild = Rules::parse("wildcards = 23\nhand_size = 4").unwrap();
+        assert!(verify_wildcards(&wild, all_hands(4)).is_empty());
+    }
+
+    #[test]
+    fn poker_hands() {
+        let rules = Rules::poker();
+        let hand = |s: &str| -> Vec<SuitedCard> {
+            s.split(' ')
+                .map(|c| SuitedCard::parse(c).unwrap())
+                .collect()
+        };
+        let eval = |s: &str| rules.evaluate_suited(&hand(s));
+
+        assert_eq!(eval("AS KS QS JS TS").category, Category::StraightFlush);
+        assert_eq!(eval("AS 2D 3C 4H 5S").category, Category::Straight);
+        assert_eq!(eval("9S 2S 7S 4S 5S").category, Category::Flush);
+        assert_eq!(eval("KH KS KD 2C 2H").category.to_string(), "Full house");
+        assert!(eval("AS 2D 3C 4H 5S") < eval("2S 3D 4C 5H 6S"));
+        assert!(eval("9S 2S 7S 4S 5S") < eval("KH KS KD 2C 2H"));
+        assert!(eval("JS JH 8C 4D 3S") < eval("JD JC AC 4S 3H"));
+        assert!(eval("2S 2H AC KD QS") < eval("3D 3C 5C 4S 2H"));
+        assert!(SuitedCard::parse("1S").is_none());
+
+        let (best, evaluation) = rules.best_hand(&hand("2H 7H 9D KH 3C TH QH")).unwrap();
+        assert_eq!(evaluation.category, Category::Flush);
+        assert!(best.iter().all(|c| c.suit == Suit::Hearts));
+        let (_, evaluation) = rules.best_hand(&hand("2H 7D 9D KH 5C 8S 6H")).unwrap();
+        assert_eq!(evaluation.category, Category::Straight);
+
+        // Camel Cards is the same evaluator without straights and flushes.
+        let cards = |s: &str| s.chars().map(Card::from_char).collect::<Vec<_>>();
+        assert_eq!(
+            Rules::standard().evaluate(&cards("23456")).category,
+            Category::HighCard
+        );
+        let straights = Rules::parse("straights = true").unwrap();
+        assert_eq!(
+            straights.evaluate(&cards("23456")).category,
+            Category::Straight
+        );
+    }
+
+    #[test]
+    fn type_probabilities() {
+        let standard = type_frequencies(&Rules::standard());
+        assert_eq!(standard.iter().sum::<u128>(), 13u128.pow(5));
+        assert_eq!(standard[Category::FiveOfAKind as usize], 13);
+        assert_eq!(standard[Category::FourOfAKind as usize], 13 * 12 * 5);
+        assert_eq!(standard[Category::FullHouse as usize], 13 * 12 * 10);
+        assert_eq!(standard[Category::HighCard as usize], 13 * 12 * 11 * 10 * 9);
+
+        // Any hand whose other cards all match is five of a kind with jokers.
+        let jokers = type_frequencies(&Rules::jokers());
+        assert_eq!(jokers[Category::FiveOfAKind as usize], 1 + 12 * 31);
+        assert_eq!(jokers[Category::HighCard as usize], 12 * 11 * 10 * 9 * 8);
+
+        let example = include_str!("examples/day07.txt");
+        let stats = type_stats(&Rules::jokers(), &parse(example, &Rules::jokers()));
+        assert_eq!(stats.iter().map(|s| s.observed).sum::<usize>(), 5);
+        assert_eq!(stats[Category::FourOfAKind as usize].observed, 3);
+        assert!((stats.iter().map(|s| s.probability).sum::<f64>() - 1.0).abs() < 1e-9);
+
+        // Ten straights, from A2345 to TJQKA, in any order.
+        let straights = type_frequencies(&Rules::parse("straights = true").unwrap());
+        assert_eq!(straights[Category::Straight as usize], 10 * 120);
+        assert_eq!(
+            straights[Category::HighCard as usize],
+            13 * 12 * 11 * 10 * 9 - 10 * 120
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong hand size for statistics: 35")]
+    fn type_probabilities_hand_size() {
+        type_frequencies(&Rules::parse("hand_size = 35").unwrap());
+    }
 }
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
+use std::fmt::Write;
 
 use gcd::Gcd;
+use itertools::Itertools;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
@@ -11,42 +13,127 @@ struct Node {
 
 type Graph = HashMap<String, Node>;
 
-struct Input {
+// The network as parsed from the input, kept to benchmark against the
+// interned one below.
+struct StringInput {
     dir: String,
     graph: Graph,
 }
 
+// Node names are interned into dense ids in order of first appearance, so
+// that walking the network indexes a `Vec` instead of hashing strings.
+struct Input {
+    // Index of the child to follow at each step: 0 for 'L', 1 for 'R'.
+    turns: Vec<usize>,
+    graph: Vec<[u32; 2]>,
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl Input {
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        // Filled in once the node's own line is parsed.
+        self.graph.push([id, id]);
+        id
+    }
+
+    fn id(&self, name: &str) -> u32 {
+        *self
+            .ids
+            .get(name)
+            .unwrap_or_else(|| panic!("Wrong node: {name}"))
+    }
+
+    fn name(&self, id: u32) -> &str {
+        &self.names[id as usize]
+    }
+
+    fn starts(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.names.len() as u32).filter(|&id| self.name(id).ends_with('A'))
+    }
+}
+
+fn parse_line(l: &str) -> (&str, &str, &str) {
+    let mut parts = l.split(" = ");
+    let src = parts.next().unwrap();
+    let mut children = parts
+        .next()
+        .unwrap()
+        .strip_prefix('(')
+        .unwrap()
+        .strip_suffix(')')
+        .unwrap()
+        .split(", ");
+    (src, children.next().unwrap(), children.next().unwrap())
+}
+
 #[aoc_generator(day8)]
 fn parse(input: &str) -> Input {
     let mut lines = input.lines();
 
+    let turns = lines
+        .next()
+        .unwrap()
+        .bytes()
+        .map(|d| match d {
+            b'L' => 0,
+            b'R' => 1,
+            x => panic!("Wrong dir: {:?}", x),
+        })
+        .collect();
+
+    lines.next();
+
+    let mut result = Input {
+        turns,
+        graph: Vec::new(),
+        names: Vec::new(),
+        ids: HashMap::new(),
+    };
+    let mut defined = Vec::new();
+    for l in lines {
+        let (src, left, right) = parse_line(l);
+        let src = result.intern(src);
+        let children = [result.intern(left), result.intern(right)];
+        result.graph[src as usize] = children;
+        defined.resize(result.names.len(), false);
+        defined[src as usize] = true;
+    }
+    defined.resize(result.names.len(), false);
+    if let Some(id) = defined.iter().position(|&d| !d) {
+        panic!("Wrong node: {} is never defined", result.names[id]);
+    }
+    result
+}
+
+#[aoc_generator(day8, part1, Strings)]
+fn parse_strings(input: &str) -> StringInput {
+    let mut lines = input.lines();
+
     let direction = lines.next().unwrap().to_string();
 
     lines.next();
 
     let graph = lines
         .map(|l| {
-            let mut parts = l.split(" = ");
-            let src = parts.next().unwrap().to_string();
-            let mut children = parts
-                .next()
-                .unwrap()
-                .strip_prefix('(')
-                .unwrap()
-                .strip_suffix(')')
-                .unwrap()
-                .split(", ");
+            let (src, left, right) = parse_line(l);
             (
-                src,
+                src.to_string(),
                 Node {
-                    left: children.next().unwrap().to_string(),
-                    right: children.next().unwrap().to_string(),
+                    left: left.to_string(),
+                    right: right.to_string(),
                 },
             )
         })
         .collect();
 
-    Input {
+    StringInput {
         dir: direction,
         graph,
     }
@@ -54,6 +141,22 @@ fn parse(input: &str) -> Input {
 
 #[aoc(day8, part1)]
 fn part1(input: &Input) -> i64 {
+    let mut seq = input.turns.iter().cycle();
+    let mut cnt = 0;
+
+    let mut node = input.id("AAA");
+    let end = input.id("ZZZ");
+
+    while node != end {
+        cnt += 1;
+        node = input.graph[node as usize][*seq.next().unwrap()];
+    }
+
+    cnt
+}
+
+#[aoc(day8, part1, Strings)]
+fn part1_strings(input: &StringInput) -> i64 {
     let mut seq = input.dir.bytes().cycle();
     let mut cnt = 0;
 
@@ -76,37 +179,73 @@ fn part1(input: &Input) -> i64 {
     cnt
 }
 
-fn find_cycle(input: &Input, start: &String) -> (Vec<usize>, usize) {
-    let mut seq = input.dir.bytes().enumerate().cycle();
+// Everything a ghost does: it walks a tail of `cycle_start` steps, then
+// repeats the same `cycle_len` steps forever. `hits` are the steps, below
+// `cycle_start + cycle_len`, at which it stands on a node ending in 'Z'.
+struct Walk {
+    hits: Vec<usize>,
+    cycle_start: usize,
+    cycle_len: usize,
+}
+
+impl Walk {
+    fn tail_hits(&self) -> &[usize] {
+        &self.hits[..self.hits.partition_point(|&h| h < self.cycle_start)]
+    }
+
+    fn cycle_hits(&self) -> &[usize] {
+        &self.hits[self.hits.partition_point(|&h| h < self.cycle_start)..]
+    }
+
+    fn is_hit(&self, step: usize) -> bool {
+        let step = if step < self.cycle_start {
+            step
+        } else {
+            self.cycle_start + (step - self.cycle_start) % self.cycle_len
+        };
+        self.hits.binary_search(&step).is_ok()
+    }
+}
+
+fn find_cycle(input: &Input, start: u32) -> (Vec<usize>, usize) {
+    let walk = walk(input, start);
+    (walk.cycle_hits().to_vec(), walk.cycle_len)
+}
+
+fn walk(input: &Input, start: u32) -> Walk {
+    let n = input.turns.len();
+    let mut seq = input.turns.iter().enumerate().cycle();
     let mut cnt = 0;
 
-    let mut key = start;
-    let mut visited: HashMap<(&String, usize), usize> = HashMap::new();
+    let mut node = start;
+    // Step at which each (node, position in the directions) state was seen.
+    let mut visited = vec![usize::MAX; input.graph.len() * n];
 
     let (cycle_start, cycle_len) = loop {
-        let (i, d) = seq.next().unwrap();
-        if let Some(val) = visited.get(&(key, i)) {
-            break (*val, cnt - *val);
-        };
+        let (i, &turn) = seq.next().unwrap();
+        let state = node as usize * n + i;
+        if visited[state] != usize::MAX {
+            break (visited[state], cnt - visited[state]);
+        }
 
-        visited.insert((key, i), cnt);
+        visited[state] = cnt;
 
         cnt += 1;
-        let node = input.graph.get(key).unwrap();
-        match d {
-            b'R' => {
-                key = &node.right;
-            }
-            b'L' => key = &node.left,
-            x => panic!("Wrong dir: {:?}", x),
-        }
+        node = input.graph[node as usize][turn];
     };
-    let starts: Vec<usize> = visited
+    let mut hits: Vec<usize> = visited
         .into_iter()
-        .filter(|((key, _), pos)| key.ends_with('Z') && *pos >= cycle_start)
+        .enumerate()
+        .filter(|&(_, pos)| pos != usize::MAX)
+        .filter(|&(state, _)| input.name((state / n) as u32).ends_with('Z'))
         .map(|(_, pos)| pos)
         .collect();
-    (starts, cycle_len)
+    hits.sort();
+    Walk {
+        hits,
+        cycle_start,
+        cycle_len,
+    }
 }
 
 fn get_lcm(a: usize, b: usize) -> usize {
@@ -114,19 +253,191 @@ fn get_lcm(a: usize, b: usize) -> usize {
     a * (b / gcd)
 }
 
-// After debugging all starts reach only 1 vertex with 'Z' and it starts at cycle len.
-// This is easily solved as least common multiple of all cycle lengths.
+// Returns (g, x, y) with a * x + b * y = g = gcd(a, b).
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+// Combines t = r1 (mod m1) and t = r2 (mod m2) into a single congruence
+// modulo lcm(m1, m2). The moduli need not be coprime, in which case there
+// may be no solution at all.
+fn crt((r1, m1): (i128, i128), (r2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    let (g, p, _) = extended_gcd(m1, m2);
+    if (r2 - r1) % g != 0 {
+        return None;
+    }
+    let lcm = m1 / g * m2;
+    let k = ((r2 - r1) / g * p).rem_euclid(m2 / g);
+    Some(((r1 + m1 * k).rem_euclid(lcm), lcm))
+}
+
+// First step at which every ghost stands on a 'Z' node at once.
+fn earliest_meeting(walks: &[Walk]) -> Option<usize> {
+    let bound = walks.iter().map(|w| w.cycle_start).max()?;
+
+    // Until every ghost has reached its cycle, just check each step.
+    if let Some(step) = (0..bound).find(|&t| walks.iter().all(|w| w.is_hit(t))) {
+        return Some(step);
+    }
+
+    // From then on each ghost is on 'Z' exactly at some residues modulo
+    // its cycle length, one per hit in the cycle.
+    let mut solutions = vec![(0, 1)];
+    for walk in walks {
+        let len = walk.cycle_len as i128;
+        solutions = solutions
+            .iter()
+            .cartesian_product(walk.cycle_hits())
+            .filter_map(|(&s, &h)| crt(s, (h as i128 % len, len)))
+            .collect();
+        solutions.sort();
+        solutions.dedup();
+    }
+    let bound = bound as i128;
+    solutions
+        .into_iter()
+        .map(|(r, m)| r + ((bound - r).max(0) + m - 1) / m * m)
+        .min()
+        .map(|t| t as usize)
+}
+
 #[aoc(day8, part2)]
 fn part2(input: &Input) -> i64 {
-    let keys = input.graph.keys().filter(|k| k.ends_with('A'));
+    let walks: Vec<Walk> = input.starts().map(|id| walk(input, id)).collect();
+    earliest_meeting(&walks).expect("Ghosts never meet on 'Z' nodes") as i64
+}
+
+// After debugging all starts reach only 1 vertex with 'Z' and it starts at cycle len.
+// This is easily solved as least common multiple of all cycle lengths.
+#[aoc(day8, part2, Lcm)]
+fn part2_lcm(input: &Input) -> i64 {
     let mut lcm = 1;
-    for key in keys {
-        let (_, cycle_len) = find_cycle(input, key);
+    for start in input.starts() {
+        let (_, cycle_len) = find_cycle(input, start);
         lcm = get_lcm(lcm, cycle_len);
     }
     lcm as i64
 }
 
+// What part2's LCM shortcut relies on: the ghost is on 'Z' at exactly the
+// positive multiples of some period, which then divides the cycle length.
+fn hit_period(walk: &Walk) -> Option<usize> {
+    let &period = walk.hits.first()?;
+    let end = walk.cycle_start + walk.cycle_len;
+    (period > 0
+        && period >= walk.cycle_start
+        && walk.cycle_len.is_multiple_of(period)
+        && walk.hits.iter().copied().eq((period..end).step_by(period)))
+    .then_some(period)
+}
+
+fn cycle_report(input: &Input) -> String {
+    let mut out = String::new();
+    let mut walks = Vec::new();
+    for start in input.starts() {
+        let walk = walk(input, start);
+        writeln!(
+            out,
+            "{}: tail {}, cycle {}, tail hits {:?}, cycle hits {:?}, {}",
+            input.name(start),
+            walk.cycle_start,
+            walk.cycle_len,
+            walk.tail_hits(),
+            walk.cycle_hits(),
+            match hit_period(&walk) {
+                Some(period) => format!("period {period}"),
+                None => "no period (breaks the LCM assumption)".to_string(),
+            }
+        )
+        .unwrap();
+        walks.push(walk);
+    }
+    let lcm = walks.iter().fold(1, |lcm, w| get_lcm(lcm, w.cycle_len));
+    // The shortcut takes the LCM of the cycle lengths, which may be off even
+    // when every ghost has a period, or right by luck when one has none.
+    match earliest_meeting(&walks) {
+        Some(earliest) if earliest == lcm => writeln!(out, "LCM shortcut: valid ({lcm})"),
+        earliest => {
+            let earliest = earliest.map_or("never".to_string(), |t| t.to_string());
+            writeln!(
+                out,
+                "LCM shortcut: invalid (lcm {lcm}, earliest meeting {earliest})"
+            )
+        }
+    }
+    .unwrap();
+    out
+}
+
+#[aoc(day8, part2, Report)]
+fn part2_report(input: &Input) -> String {
+    cycle_report(input)
+}
+
+// Edges, as (node, turn), that a ghost from `start` keeps taking forever.
+fn cycle_edges(input: &Input, start: u32) -> Vec<(u32, usize)> {
+    let walk = walk(input, start);
+    let mut edges = Vec::new();
+    let mut node = start;
+    for step in 0..walk.cycle_start + walk.cycle_len {
+        let turn = input.turns[step % input.turns.len()];
+        if step >= walk.cycle_start {
+            edges.push((node, turn));
+        }
+        node = input.graph[node as usize][turn];
+    }
+    edges
+}
+
+const GHOST_COLORS: [&str; 6] = ["red", "blue", "darkgreen", "orange", "purple", "brown"];
+
+// Graphviz rendering of the network, with '..A' and '..Z' nodes filled and
+// the edges of each ghost's cycle drawn in that ghost's colour.
+fn to_dot(input: &Input) -> String {
+    let mut colors: HashMap<(u32, usize), Vec<&str>> = HashMap::new();
+    for (ghost, start) in input.starts().enumerate() {
+        let color = GHOST_COLORS[ghost % GHOST_COLORS.len()];
+        for edge in cycle_edges(input, start) {
+            let ghosts = colors.entry(edge).or_default();
+            if !ghosts.contains(&color) {
+                ghosts.push(color);
+            }
+        }
+    }
+
+    let mut out = String::from("digraph day8 {\n");
+    for (id, name) in input.names.iter().enumerate() {
+        let style = if name.ends_with('A') {
+            " [style=filled, fillcolor=palegreen, shape=box]"
+        } else if name.ends_with('Z') {
+            " [style=filled, fillcolor=salmon, shape=doublecircle]"
+        } else {
+            ""
+        };
+        writeln!(out, "  \"{name}\"{style};").unwrap();
+        for (turn, label) in ["L", "R"].into_iter().enumerate() {
+            let child = input.name(input.graph[id][turn]);
+            let color = match colors.get(&(id as u32, turn)) {
+                Some(ghosts) => format!(", color=\"{}\", penwidth=2", ghosts.join(":")),
+                None => String::new(),
+            };
+            writeln!(out, "  \"{name}\" -> \"{child}\" [label={label}{color}];").unwrap();
+        }
+    }
+    out += "}\n";
+    out
+}
+
+#[aoc(day8, part2, Dot)]
+fn part2_dot(input: &Input) -> String {
+    to_dot(input)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,6 +456,23 @@ AAA = (BBB, BBB)
 BBB = (AAA, ZZZ)
 ZZZ = (ZZZ, ZZZ)";
         assert_eq!(part1(&parse(example)), 6);
+        assert_eq!(part1_strings(&parse_strings(example)), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "BBB is never defined")]
+    fn undefined_node() {
+        parse("L\n\nAAA = (BBB, ZZZ)\nZZZ = (ZZZ, ZZZ)");
+    }
+
+    #[test]
+    fn interned_names() {
+        let input = parse(include_str!("examples/day08.txt"));
+        let aaa = input.id("AAA");
+        assert_eq!((aaa, input.name(aaa)), (0, "AAA"));
+        let [left, right] = input.graph[aaa as usize];
+        assert_eq!((input.name(left), input.name(right)), ("BBB", "CCC"));
+        assert_eq!(input.starts().collect::<Vec<_>>(), vec![aaa]);
     }
 
     #[test]
@@ -161,4 +489,99 @@ ZZZ = (ZZZ, ZZZ)";
 XXX = (XXX, XXX)";
         assert_eq!(part2(&parse(example)), 6);
     }
+
+    #[test]
+    fn part2_offset_cycles() {
+        // 1A is on 'Z' at even steps, 2A at steps 1, 4, 7, ...
+        let example = "L
+
+1A = (1B, 1B)
+1B = (1Z, 1Z)
+1Z = (1B, 1B)
+2A = (2Z, 2Z)
+2Z = (2B, 2B)
+2B = (2C, 2C)
+2C = (2Z, 2Z)";
+        let input = parse(example);
+        assert_eq!(part2(&input), 4);
+        assert_eq!(part2_lcm(&input), 6);
+
+        // 3A only passes 3Z once, before settling into its cycle.
+        let example = "L
+
+2A = (2Z, 2Z)
+2Z = (2B, 2B)
+2B = (2C, 2C)
+2C = (2Z, 2Z)
+3A = (3Z, 3Z)
+3Z = (3B, 3B)
+3B = (3B, 3B)";
+        let input = parse(example);
+        let walk = walk(&input, input.id("3A"));
+        assert_eq!((walk.hits, walk.cycle_start), (vec![1], 2));
+        assert_eq!(part2(&input), 1);
+    }
+
+    #[test]
+    fn crt_non_coprime() {
+        assert_eq!(crt((2, 4), (4, 6)), Some((10, 12)));
+        assert_eq!(crt((1, 2), (0, 4)), None);
+        assert_eq!(crt((0, 1), (3, 5)), Some((3, 5)));
+    }
+
+    #[test]
+    fn dot_export() {
+        let example = "LR
+
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+XXX = (XXX, XXX)";
+        let dot = to_dot(&parse(example));
+        assert!(dot.starts_with("digraph day8 {\n"));
+        assert!(dot.contains("\"11A\" [style=filled, fillcolor=palegreen, shape=box];"));
+        assert!(dot.contains("\"22Z\" [style=filled, fillcolor=salmon, shape=doublecircle];"));
+        assert!(dot.contains("\"11A\" -> \"11B\" [label=L];"));
+        assert!(dot.contains("\"11B\" -> \"11Z\" [label=R, color=\"red\", penwidth=2];"));
+        assert!(dot.contains("\"22B\" -> \"22C\" [label=L, color=\"blue\", penwidth=2];"));
+        assert!(dot.contains("\"XXX\" -> \"XXX\" [label=R];"));
+    }
+
+    #[test]
+    fn cycle_reports() {
+        let example = "LR
+
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+XXX = (XXX, XXX)";
+        assert_eq!(
+            cycle_report(&parse(example)),
+            "11A: tail 1, cycle 2, tail hits [], cycle hits [2], period 2
+22A: tail 1, cycle 6, tail hits [], cycle hits [3, 6], period 3
+LCM shortcut: valid (6)
+"
+        );
+
+        let example = "L
+
+1A = (1B, 1B)
+1B = (1Z, 1Z)
+1Z = (1B, 1B)
+2A = (2Z, 2Z)
+2Z = (2B, 2B)
+2B = (2C, 2C)
+2C = (2Z, 2Z)";
+        let report = cycle_report(&parse(example));
+        assert!(report.contains("2A: tail 1, cycle 3, tail hits [], cycle hits [1], no period"));
+        assert!(report.ends_with("LCM shortcut: invalid (lcm 6, earliest meeting 4)\n"));
+    }
 }
@@ -1,10 +1,10 @@
 mod day1;
 mod day2;
 mod day3;
-mod day4;
-mod day5;
+pub mod day4;
+pub mod day5;
 mod day6;
-mod day7;
+pub mod day7;
 mod day8;
 mod day9;
 